@@ -0,0 +1,406 @@
+use anyhow::{bail, Result};
+use bp7::administrative_record::{AdministrativeRecord, DELIVERED_BUNDLE};
+use bp7::{Bundle, EndpointID};
+use clap::{crate_authors, crate_version, App, Arg};
+use crossbeam_channel::{unbounded, Sender};
+use dtn7_plus::client::DtnClient;
+use humantime::{format_duration, parse_duration};
+use std::convert::{TryFrom, TryInto};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use ws::{Builder, CloseCode, Handler, Handshake, Message};
+
+use dtnchat::options::{BundleOptions, Reports};
+use dtnchat::ping::{unix_now_ms, Pings};
+use dtnchat::proto::{Control, Ping};
+use dtnchat::recent::Recent;
+use dtnchat::roster;
+use dtnchat::settings::Settings;
+use dtnchat::stats::{delay_stats, Stats};
+use dtnchat::transcript::Transcript;
+use dtnchat::ws::{send_listener, Outgoing, WsCommand};
+
+/// Receives echoes and status reports for the probes of all virtual senders
+struct BenchConnection {
+    localnode: EndpointID,
+    out: ws::Sender,
+    stats: Arc<Mutex<Stats>>,
+    pings: Arc<Mutex<Pings>>,
+    ready: Sender<()>,
+}
+
+impl BenchConnection {
+    fn on_bundle(&self, bndl: Bundle, size: usize) {
+        if bndl.is_administrative_record() {
+            if let Some(AdministrativeRecord::BundleStatusReport(report)) = bndl
+                .payload()
+                .and_then(|p| serde_cbor::from_slice::<AdministrativeRecord>(p).ok())
+            {
+                if let Some(info) = report
+                    .status_information
+                    .get(DELIVERED_BUNDLE as usize)
+                    .filter(|i| i.asserted)
+                {
+                    let time = info.time;
+                    self.stats
+                        .lock()
+                        .unwrap()
+                        .delivered(&report.refbundle(), time);
+                }
+            }
+            return;
+        }
+        self.stats.lock().unwrap().received(&bndl, size);
+        if let Some(Control::Echo(echo)) = bndl.payload().and_then(|p| Control::from_cbor(p)) {
+            self.pings.lock().unwrap().echo(&echo, unix_now_ms());
+        }
+    }
+}
+
+impl Handler for BenchConnection {
+    fn on_open(&mut self, _: Handshake) -> ws::Result<()> {
+        self.out.send(format!("/subscribe {}", self.localnode))?;
+        self.out.send("/bundle".to_string())?;
+        Ok(())
+    }
+
+    fn on_message(&mut self, msg: Message) -> ws::Result<()> {
+        match msg {
+            Message::Text(txt) => {
+                if txt == "subscribed" {
+                    self.ready.send(()).ok();
+                } else if !txt.starts_with("200") {
+                    eprintln!("Unexpected response: {}", txt);
+                    self.out.close(CloseCode::Error)?;
+                }
+            }
+            Message::Binary(bin) => {
+                let size = bin.len();
+                match Bundle::try_from(bin) {
+                    Ok(bndl) => self.on_bundle(bndl, size),
+                    Err(e) => eprintln!("Could not decode bundle: {}", e),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Endpoint of a peer given as node name, ipn node number or full endpoint
+fn target_eid(target: &str) -> Result<EndpointID> {
+    if target.contains(':') {
+        return Ok(target.try_into()?);
+    }
+    roster::chat_eid(target, false)
+}
+
+/// Send `count` probes of about `size` bytes to `dst` at `rate` per second
+#[allow(clippy::too_many_arguments)]
+fn virtual_sender(
+    pings: Arc<Mutex<Pings>>,
+    tx: Sender<WsCommand>,
+    src: EndpointID,
+    dst: EndpointID,
+    options: BundleOptions,
+    lifetime: Duration,
+    count: u32,
+    rate: f64,
+    size: usize,
+) {
+    let id = pings.lock().unwrap().start(&dst.to_string(), count);
+    let interval = Duration::from_secs_f64(1.0 / rate);
+    let start = Instant::now();
+    for seq in 1..=count {
+        // keep the rate even if sending falls behind
+        if let Some(wait) = (interval * (seq - 1)).checked_sub(start.elapsed()) {
+            thread::sleep(wait);
+        }
+        let sent = unix_now_ms();
+        let mut ping = Ping {
+            id: id.clone(),
+            seq,
+            sent,
+            ..Default::default()
+        };
+        let overhead = Control::Ping(ping.clone()).to_cbor().len();
+        ping.padding = "x".repeat(size.saturating_sub(overhead));
+        pings.lock().unwrap().sent(&id, seq, sent);
+        let data = Outgoing {
+            src: src.clone(),
+            dst: dst.clone(),
+            options: options.clone(),
+            lifetime,
+            data: Control::Ping(ping).to_cbor(),
+        };
+        if tx.send(WsCommand::SendData(data)).is_err() {
+            return;
+        }
+    }
+}
+
+fn format_ms(ms: u64) -> String {
+    format_duration(Duration::from_millis(ms)).to_string()
+}
+
+fn main() -> Result<()> {
+    let matches = App::new("dtnchat-bench")
+        .version(crate_version!())
+        .author(crate_authors!())
+        .about("Load generator measuring throughput, delivery ratio and delay of dtnchat traffic")
+        .arg(
+            Arg::with_name("port")
+                .short("p")
+                .long("port")
+                .value_name("PORT")
+                .help("Local web port (default = 3000)")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ipv6")
+                .short("6")
+                .long("ipv6")
+                .help("Use IPv6")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("senders")
+                .short("n")
+                .long("senders")
+                .value_name("N")
+                .help("Number of virtual senders (default = 1)")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("count")
+                .short("c")
+                .long("count")
+                .value_name("COUNT")
+                .help("Messages per sender (default = 10)")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rate")
+                .short("r")
+                .long("rate")
+                .value_name("RATE")
+                .help("Messages per second and sender (default = 1)")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("size")
+                .short("s")
+                .long("size")
+                .value_name("BYTES")
+                .help("Payload size of each message (default = 100)")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("lifetime")
+                .short("l")
+                .long("lifetime")
+                .value_name("DURATION")
+                .help("Bundle lifetime (default = 1h)")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("wait")
+                .short("w")
+                .long("wait")
+                .value_name("DURATION")
+                .help("Time to wait for echoes and reports after sending (default = 30s)")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("targets")
+                .value_name("PEER")
+                .help("Peers to send to as node name, ipn node number or endpoint, e.g. dtn://group/~sms for a group; senders are spread over them")
+                .required(true)
+                .multiple(true),
+        )
+        .get_matches();
+
+    let port = std::env::var("DTN_WEB_PORT").unwrap_or_else(|_| "3000".into());
+    let port = matches.value_of("port").unwrap_or(&port);
+    let localhost = if matches.is_present("ipv6") {
+        "[::1]"
+    } else {
+        "127.0.0.1"
+    };
+    let senders: usize = matches.value_of("senders").unwrap_or("1").parse()?;
+    let count: u32 = matches.value_of("count").unwrap_or("10").parse()?;
+    let rate: f64 = matches.value_of("rate").unwrap_or("1").parse()?;
+    let size: usize = matches.value_of("size").unwrap_or("100").parse()?;
+    let lifetime = parse_duration(matches.value_of("lifetime").unwrap_or("1h"))?;
+    let wait = parse_duration(matches.value_of("wait").unwrap_or("30s"))?;
+    if senders == 0 || !rate.is_finite() || rate <= 0.0 {
+        bail!("need at least one sender and a positive, finite rate");
+    }
+    let targets = matches
+        .values_of("targets")
+        .unwrap()
+        .map(target_eid)
+        .collect::<Result<Vec<_>>>()?;
+
+    let client = DtnClient::with_host_and_port(
+        localhost.into(),
+        port.parse::<u16>().expect("invalid port number"),
+    );
+    let localnode: EndpointID = client.local_node_id()?;
+    let endpoint = if localnode.scheme() == "dtn" {
+        localnode.new_endpoint("bench")?
+    } else {
+        localnode.new_endpoint("768")?
+    };
+    client.register_application_endpoint(&endpoint.to_string())?;
+
+    let stats = Arc::new(Mutex::new(Stats::new()));
+    let pings = Arc::new(Mutex::new(Pings::new()));
+    let (tx, rx) = unbounded::<WsCommand>();
+    let (ready_tx, ready_rx) = unbounded::<()>();
+    let settings = Arc::new(Mutex::new(Settings::default()));
+
+    let stats2 = stats.clone();
+    let pings2 = pings.clone();
+    let endpoint2 = endpoint.clone();
+    let mut ws = Builder::new()
+        .build(move |out: ws::Sender| {
+            let out2 = out.clone();
+            let rx2 = rx.clone();
+            let settings2 = settings.clone();
+            let stats3 = stats2.clone();
+            thread::spawn(move || {
+                send_listener(
+                    rx2,
+                    out2,
+                    None,
+                    None,
+                    // probes are control messages, nothing ends up in the transcript
+                    Arc::new(Mutex::new(Transcript::new())),
+                    Arc::new(Mutex::new(Recent::new())),
+                    settings2,
+                    stats3,
+                )
+            });
+            BenchConnection {
+                localnode: endpoint2.clone(),
+                out,
+                stats: stats2.clone(),
+                pings: pings2.clone(),
+                ready: ready_tx.clone(),
+            }
+        })
+        .unwrap();
+    ws.connect(url::Url::parse(&format!("ws://{}:{}/ws", localhost, port))?)?;
+    thread::spawn(|| {
+        ws.run().unwrap();
+    });
+    if ready_rx.recv_timeout(Duration::from_secs(10)).is_err() {
+        bail!("could not subscribe to {} at dtnd", endpoint);
+    }
+
+    let options = BundleOptions {
+        reports: Reports::delivery(),
+        report_to: Some(endpoint.clone()),
+        ..Default::default()
+    };
+    println!(
+        "{} senders sending {} messages of {} bytes at {}/s each to {} peers",
+        senders,
+        count,
+        size,
+        rate,
+        targets.len()
+    );
+    let start = Instant::now();
+    let handles: Vec<_> = (0..senders)
+        .map(|i| {
+            let pings = pings.clone();
+            let tx = tx.clone();
+            let src = endpoint.clone();
+            let dst = targets[i % targets.len()].clone();
+            let options = options.clone();
+            thread::spawn(move || {
+                virtual_sender(pings, tx, src, dst, options, lifetime, count, rate, size)
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().ok();
+    }
+    // wait until the send listener handed everything to dtnd
+    while !tx.is_empty() {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "Sending done after {}, waiting up to {} for echoes and reports",
+        format_duration(Duration::from_millis(elapsed.as_millis() as u64)),
+        format_duration(wait)
+    );
+    let deadline = Instant::now() + wait;
+    while Instant::now() < deadline {
+        let answered = pings
+            .lock()
+            .unwrap()
+            .sessions()
+            .iter()
+            .all(|s| s.answered.len() == s.sent());
+        if answered && stats.lock().unwrap().pending() == 0 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    let stats = stats.lock().unwrap();
+    let secs = elapsed.as_secs_f64().max(0.001);
+    println!();
+    println!(
+        "throughput:     {:.1} messages/s, {:.1} bytes/s",
+        stats.total.bundles_sent as f64 / secs,
+        stats.total.bytes_sent as f64 / secs
+    );
+    for line in stats.total.describe() {
+        println!("{}", line);
+    }
+    let pings = pings.lock().unwrap();
+    let sent: usize = pings.sessions().iter().map(|s| s.sent()).sum();
+    let rtts: Vec<u64> = pings
+        .sessions()
+        .iter()
+        .flat_map(|s| s.answered.iter().map(|p| p.rtt))
+        .collect();
+    println!(
+        "echoed:         {} of {} ({:.1}%)",
+        rtts.len(),
+        sent,
+        100.0 * rtts.len() as f64 / sent.max(1) as f64
+    );
+    match delay_stats(&rtts) {
+        Some((min, median, max)) => println!(
+            "round-trip:     min {}, median {}, max {}",
+            format_ms(min),
+            format_ms(median),
+            format_ms(max)
+        ),
+        None => println!("round-trip:     no echoes"),
+    }
+    if stats.peers.len() > 1 {
+        for (peer, c) in &stats.peers {
+            println!();
+            println!("{}:", peer);
+            for line in c.describe() {
+                println!("  {}", line);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use crate::roster::unix_now;
+use bp7::canonical::BUNDLE_AGE_BLOCK;
+use bp7::dtntime::{DtnTime, SECONDS1970_TO2K};
+use bp7::Bundle;
+use std::collections::HashMap;
+
+/// Creation times further ahead of our clock than this are reported as clock skew
+pub const SKEW_WARN_SECS: i64 = 5 * 60;
+
+/// End of the year 9999, later times come from broken clocks and are not converted
+pub const MAX_UNIX_TIME: u64 = 253_402_300_799;
+
+/// Unix time of a DTN time, `None` if it lies beyond [`MAX_UNIX_TIME`]
+pub fn dtn_to_unix(time: DtnTime) -> Option<u64> {
+    let unix = (time / 1000).checked_add(SECONDS1970_TO2K)?;
+    Some(unix).filter(|t| *t <= MAX_UNIX_TIME)
+}
+
+/// Unix time at which `bndl` was created and whether it had to be estimated
+///
+/// Senders without a synchronized clock use DTN time 0. For those, and for
+/// times beyond [`MAX_UNIX_TIME`], the creation time is derived from the
+/// Bundle Age block if present, otherwise the receive time is used.
+pub fn creation_time(bndl: &Bundle) -> (u64, bool) {
+    let created = bndl.primary.creation_timestamp.dtntime();
+    if created != 0 {
+        if let Some(created) = dtn_to_unix(created) {
+            return (created, false);
+        }
+    }
+    let age_secs = bndl
+        .extension_block_by_type(BUNDLE_AGE_BLOCK)
+        .and_then(|b| b.bundle_age_get())
+        .map_or(0, |ms| (ms / 1000) as u64);
+    (unix_now().saturating_sub(age_secs), true)
+}
+
+/// Difference between the clocks of peers and our own clock
+#[derive(Debug, Default)]
+pub struct ClockSkew {
+    /// Seconds the last creation time of a peer was ahead of our clock
+    skew: HashMap<String, i64>,
+}
+
+impl ClockSkew {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    /// Record the creation time of a bundle from `node` received now
+    ///
+    /// Returns the skew if it newly exceeds [`SKEW_WARN_SECS`].
+    pub fn record(&mut self, node: &str, created: u64) -> Option<i64> {
+        let skew = created.min(MAX_UNIX_TIME) as i64 - unix_now() as i64;
+        let previous = self.skew.insert(node.to_string(), skew);
+        if skew > SKEW_WARN_SECS && previous.is_none_or(|p| p <= SKEW_WARN_SECS) {
+            Some(skew)
+        } else {
+            None
+        }
+    }
+    pub fn get(&self, node: &str) -> Option<i64> {
+        self.skew.get(node).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bp7::canonical::new_bundle_age_block;
+    use bp7::flags::BlockControlFlags;
+    use bp7::{bundle, CreationTimestamp, EndpointID};
+    use std::convert::TryInto;
+
+    fn bundle_created_at(time: DtnTime) -> Bundle {
+        let src: EndpointID = "dtn://alice/sms".try_into().unwrap();
+        let dst: EndpointID = "dtn://bob/sms".try_into().unwrap();
+        let mut bndl = bundle::new_std_payload_bundle(src, dst, b"hi".to_vec());
+        bndl.primary.creation_timestamp = CreationTimestamp::with_time_and_seq(time, 0);
+        bndl
+    }
+
+    #[test]
+    fn converts_dtn_times() {
+        assert_eq!(dtn_to_unix(0), Some(SECONDS1970_TO2K));
+        assert_eq!(dtn_to_unix(1_500), Some(SECONDS1970_TO2K + 1));
+        assert_eq!(dtn_to_unix(u64::MAX), None);
+        let last = (MAX_UNIX_TIME - SECONDS1970_TO2K) * 1000;
+        assert_eq!(dtn_to_unix(last), Some(MAX_UNIX_TIME));
+        assert_eq!(dtn_to_unix(last + 1000), None);
+    }
+
+    #[test]
+    fn creation_time_of_synchronized_sender() {
+        let bndl = bundle_created_at(10_000);
+        assert_eq!(creation_time(&bndl), (SECONDS1970_TO2K + 10, false));
+    }
+
+    #[test]
+    fn creation_time_without_clock_is_estimated() {
+        let before = unix_now();
+        let (created, estimated) = creation_time(&bundle_created_at(0));
+        assert!(estimated);
+        assert!(created >= before && created <= unix_now());
+
+        let mut bndl = bundle_created_at(0);
+        bndl.add_canonical_block(new_bundle_age_block(
+            0,
+            BlockControlFlags::empty(),
+            3_600_000,
+        ));
+        let (created, estimated) = creation_time(&bndl);
+        assert!(estimated);
+        assert!(created + 3600 >= before && created + 3600 <= unix_now());
+    }
+
+    #[test]
+    fn absurd_creation_times_are_estimated() {
+        let before = unix_now();
+        let (created, estimated) = creation_time(&bundle_created_at(u64::MAX));
+        assert!(estimated);
+        assert!(created >= before && created <= unix_now());
+    }
+
+    #[test]
+    fn skew_is_reported_once_it_exceeds_the_limit() {
+        let mut skew = ClockSkew::new();
+        assert_eq!(skew.record("alice", unix_now()), None);
+        let ahead = skew.record("alice", unix_now() + 3600).unwrap();
+        assert!((3599..=3600).contains(&ahead));
+        // no repeated warning while the clock stays ahead
+        assert_eq!(skew.record("alice", unix_now() + 3600), None);
+        assert_eq!(skew.record("alice", unix_now()), None);
+        assert!(skew.record("alice", unix_now() + 3600).is_some());
+        assert!(skew.get("bob").is_none());
+        // too far ahead to convert, still no overflow
+        assert!(skew.record("bob", u64::MAX).is_some());
+    }
+}
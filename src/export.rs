@@ -0,0 +1,128 @@
+use crate::timefmt::local_time;
+use crate::transcript::{DeliveryState, Entry};
+use anyhow::{bail, Result};
+use chrono::Local;
+use humantime::format_duration;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    /// Transcript entries with the primary block metadata of their bundles
+    Json,
+    Html,
+    Text,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(Format::Json),
+            "html" => Ok(Format::Html),
+            "txt" | "text" => Ok(Format::Text),
+            _ => bail!("unknown export format {}, use json, html or txt", s),
+        }
+    }
+}
+
+fn lifetime(e: &Entry) -> String {
+    format_duration(Duration::from_secs(e.lifetime)).to_string()
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Self-contained HTML page with one table row per message
+fn to_html(title: &str, entries: &[&Entry]) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html><head><meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>dtnchat: {}</title>", escape_html(title))?;
+    writeln!(
+        out,
+        "<style>body{{font-family:monospace;background:#111;color:#ddd}}\
+         td{{padding:2px 8px;vertical-align:top}}.time{{color:#0aa}}.src{{color:#5f5}}\
+         .dst{{color:#0a0}}.meta{{color:#777}}.out{{background:#1a1a2a}}</style>"
+    )?;
+    writeln!(out, "</head><body>")?;
+    writeln!(out, "<h1>dtnchat: {}</h1>", escape_html(title))?;
+    writeln!(out, "<table>")?;
+    for e in entries {
+        let class = if e.outgoing { " class=\"out\"" } else { "" };
+        writeln!(
+            out,
+            "<tr{}><td class=\"time\">{}</td><td class=\"src\">{}</td><td class=\"dst\">&gt; {}</td><td>{}</td><td class=\"meta\" title=\"{}\">{}, {}</td></tr>",
+            class,
+            local_time(e.time).format("%F %T"),
+            escape_html(&e.src),
+            escape_html(&e.dst),
+            escape_html(&e.msg).replace('\n', "<br>"),
+            escape_html(&e.bid),
+            lifetime(e),
+            e.state
+        )?;
+    }
+    writeln!(out, "</table>")?;
+    writeln!(out, "</body></html>")?;
+    Ok(out)
+}
+
+/// irssi style log with day change markers
+fn to_text(title: &str, entries: &[&Entry]) -> Result<String> {
+    let mut out = String::new();
+    let mut day = None;
+    writeln!(
+        out,
+        "--- Log opened {} ({})",
+        Local::now().format("%a %b %d %T %Y"),
+        title
+    )?;
+    for e in entries {
+        let datetime = local_time(e.time);
+        if day != Some(datetime.date()) {
+            writeln!(out, "--- Day changed {}", datetime.format("%a %b %d %Y"))?;
+            day = Some(datetime.date());
+        }
+        let state = if e.state == DeliveryState::Received {
+            String::new()
+        } else {
+            format!(", {}", e.state)
+        };
+        writeln!(
+            out,
+            "{} <{}> [{}] {} (lifetime {}{})",
+            datetime.format("%T"),
+            e.src,
+            e.dst,
+            e.msg.replace('\n', "\n         "),
+            lifetime(e),
+            state
+        )?;
+    }
+    writeln!(
+        out,
+        "--- Log closed {}",
+        Local::now().format("%a %b %d %T %Y")
+    )?;
+    Ok(out)
+}
+
+/// Write the given transcript entries to `path`
+pub fn export(title: &str, entries: &[&Entry], format: Format, path: &Path) -> Result<()> {
+    let content = match format {
+        Format::Json => serde_json::to_string_pretty(entries)?,
+        Format::Html => to_html(title, entries)?,
+        Format::Text => to_text(title, entries)?,
+    };
+    fs::write(path, content)?;
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use anyhow::Result;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Ignored endpoints and patterns, one per line
+pub const IGNORE_FILE: &str = "dtnchat.ignore";
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+pub enum Verdict {
+    Show,
+    Ignored,
+    /// Sender exceeded the rate limit, `true` the first time this happens
+    Muted(bool),
+}
+
+/// Match `text` against a pattern where `*` matches any sequence of characters
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+    let mut rest = text;
+    for (i, part) in parts.iter().enumerate() {
+        if i == 0 {
+            if !rest.starts_with(part) {
+                return false;
+            }
+            rest = &rest[part.len()..];
+        } else if i == parts.len() - 1 {
+            return rest.ends_with(part);
+        } else if let Some(pos) = rest.find(part) {
+            rest = &rest[pos + part.len()..];
+        } else {
+            return false;
+        }
+    }
+    true
+}
+
+/// Senders and groups whose bundles are not shown
+#[derive(Debug, Default)]
+pub struct IgnoreList {
+    patterns: Vec<String>,
+    /// Maximum number of bundles per sender and minute, `None` for no limit
+    pub rate_limit: Option<usize>,
+    arrivals: HashMap<String, VecDeque<Instant>>,
+    muted: HashSet<String>,
+}
+
+impl IgnoreList {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    pub fn load() -> Result<Self> {
+        let mut list = IgnoreList::new();
+        match fs::read_to_string(IGNORE_FILE) {
+            Ok(content) => {
+                list.patterns = content
+                    .lines()
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(list)
+    }
+    pub fn save(&self) -> Result<()> {
+        let mut content = self.patterns.join("\n");
+        content.push('\n');
+        fs::write(IGNORE_FILE, content)?;
+        Ok(())
+    }
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+    pub fn add(&mut self, pattern: &str) -> bool {
+        if self.patterns.iter().any(|p| p == pattern) {
+            return false;
+        }
+        self.patterns.push(pattern.to_string());
+        true
+    }
+    /// Remove a pattern, also lifts an automatic mute of that sender
+    pub fn remove(&mut self, pattern: &str) -> bool {
+        self.muted.remove(pattern);
+        self.arrivals.remove(pattern);
+        let len = self.patterns.len();
+        self.patterns.retain(|p| p != pattern);
+        len != self.patterns.len()
+    }
+    /// Whether a node name or endpoint matches one of the patterns
+    pub fn is_ignored(&self, node: &str, eid: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|p| glob_match(p, node) || glob_match(p, eid))
+    }
+    /// Decide whether a bundle from `src` to `dst` should be shown
+    pub fn check(&mut self, src_node: &str, src: &str, dst_node: &str, dst: &str) -> Verdict {
+        if self.is_ignored(src_node, src) || self.is_ignored(dst_node, dst) {
+            return Verdict::Ignored;
+        }
+        if let Some(limit) = self.rate_limit {
+            let now = Instant::now();
+            let arrivals = self.arrivals.entry(src_node.to_string()).or_default();
+            while arrivals
+                .front()
+                .is_some_and(|t| now.duration_since(*t) > RATE_WINDOW)
+            {
+                arrivals.pop_front();
+            }
+            arrivals.push_back(now);
+            if arrivals.len() > limit {
+                return Verdict::Muted(self.muted.insert(src_node.to_string()));
+            }
+            self.muted.remove(src_node);
+        }
+        Verdict::Show
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_without_star_matches_exactly() {
+        assert!(glob_match("alice", "alice"));
+        assert!(!glob_match("alice", "alice2"));
+        assert!(!glob_match("alice", "malice"));
+    }
+
+    #[test]
+    fn glob_star_matches_any_sequence() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("spam*", "spambot"));
+        assert!(glob_match("spam*", "spam"));
+        assert!(glob_match("*bot", "spambot"));
+        assert!(glob_match("dtn://*/sms", "dtn://node1/sms"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("a*b*c", "axxcyyb"));
+        assert!(!glob_match("*bot", "bots"));
+    }
+
+    #[test]
+    fn glob_parts_do_not_overlap() {
+        assert!(!glob_match("ab*b", "ab"));
+        assert!(glob_match("ab*b", "abb"));
+    }
+}
@@ -0,0 +1,164 @@
+use crate::clock::{creation_time, dtn_to_unix};
+use crate::proto::Control;
+use crate::roster::unix_now;
+use crate::timefmt::{format_time, TimeFormat};
+use bp7::canonical::{
+    CanonicalData, BUNDLE_AGE_BLOCK, HOP_COUNT_BLOCK, PAYLOAD_BLOCK, PREVIOUS_NODE_BLOCK,
+};
+use bp7::crc::CrcValue;
+use bp7::dtntime::DtnTimeHelpers;
+use bp7::flags::{BundleControlFlags, BundleValidation};
+use bp7::Bundle;
+use dtn7_plus::sms::SMSBundle;
+use humantime::format_duration;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+const FLAG_NAMES: &[(BundleControlFlags, &str)] = &[
+    (BundleControlFlags::BUNDLE_IS_FRAGMENT, "fragment"),
+    (
+        BundleControlFlags::BUNDLE_ADMINISTRATIVE_RECORD_PAYLOAD,
+        "admin-record",
+    ),
+    (
+        BundleControlFlags::BUNDLE_MUST_NOT_FRAGMENTED,
+        "must-not-fragment",
+    ),
+    (
+        BundleControlFlags::BUNDLE_REQUEST_USER_APPLICATION_ACK,
+        "app-ack",
+    ),
+    (
+        BundleControlFlags::BUNDLE_REQUEST_STATUS_TIME,
+        "status-time",
+    ),
+    (
+        BundleControlFlags::BUNDLE_STATUS_REQUEST_RECEPTION,
+        "report-reception",
+    ),
+    (
+        BundleControlFlags::BUNDLE_STATUS_REQUEST_FORWARD,
+        "report-forwarding",
+    ),
+    (
+        BundleControlFlags::BUNDLE_STATUS_REQUEST_DELIVERY,
+        "report-delivery",
+    ),
+    (
+        BundleControlFlags::BUNDLE_STATUS_REQUEST_DELETION,
+        "report-deletion",
+    ),
+];
+
+fn crc_name(crc: &CrcValue) -> &'static str {
+    match crc {
+        CrcValue::CrcNo => "none",
+        CrcValue::Crc16Empty | CrcValue::Crc16(_) => "crc16",
+        CrcValue::Crc32Empty | CrcValue::Crc32(_) => "crc32",
+        CrcValue::Unknown(_) => "unknown",
+    }
+}
+
+fn block_name(block_type: u64) -> String {
+    match block_type {
+        PAYLOAD_BLOCK => "payload".into(),
+        PREVIOUS_NODE_BLOCK => "previous node".into(),
+        BUNDLE_AGE_BLOCK => "bundle age".into(),
+        HOP_COUNT_BLOCK => "hop count".into(),
+        other => format!("type {}", other),
+    }
+}
+
+/// What the payload contains as far as dtnchat understands it
+fn payload_summary(bndl: &Bundle) -> String {
+    let size = bndl.payload().map_or(0, |p| p.len());
+    if let Ok(sms) = SMSBundle::try_from(bndl.clone()) {
+        let text = sms.msg().len();
+        if sms.compression() && text > 0 {
+            format!(
+                "SMS, {} bytes, {} bytes of text compressed to {:.0}%",
+                size,
+                text,
+                100.0 * size as f64 / text as f64
+            )
+        } else {
+            format!("SMS, {} bytes, {} bytes of text uncompressed", size, text)
+        }
+    } else if let Some(ctrl) = bndl.payload().and_then(|p| Control::from_cbor(p)) {
+        let kind = format!("{:?}", ctrl);
+        let kind = kind.split('(').next().unwrap_or_default();
+        format!("dtnchat {}, {} bytes", kind.to_lowercase(), size)
+    } else {
+        format!("{} bytes", size)
+    }
+}
+
+/// Human readable description of all blocks of a bundle
+pub fn describe(bndl: &Bundle, time_format: TimeFormat) -> Vec<String> {
+    let primary = &bndl.primary;
+    let flags = primary.bundle_control_flags.flags();
+    let flag_names: Vec<&str> = FLAG_NAMES
+        .iter()
+        .filter(|(flag, _)| flags.contains(*flag))
+        .map(|(_, name)| *name)
+        .collect();
+    let (created, estimated) = creation_time(bndl);
+    let expires = created.saturating_add(primary.lifetime.as_secs());
+    let mut lines = vec![
+        format!("Bundle-Id:   {}", bndl.id()),
+        format!("Source:      {}", primary.source),
+        format!("Destination: {}", primary.destination),
+        format!("Report-To:   {}", primary.report_to),
+        format!(
+            "Flags:       {:#x} {}",
+            primary.bundle_control_flags,
+            flag_names.join(" ")
+        ),
+        format!(
+            "Created:     {} ({}){}",
+            match primary.creation_timestamp.dtntime() {
+                t if dtn_to_unix(t).is_some() => t.string(),
+                t => format!("{} ms after 2000, out of range", t),
+            },
+            format_time(created, time_format),
+            if estimated {
+                ", sender without clock"
+            } else {
+                ""
+            }
+        ),
+        format!("Sequence:    {}", primary.creation_timestamp.seqno()),
+        format!(
+            "Lifetime:    {}, {}",
+            format_duration(primary.lifetime),
+            if expires > unix_now() {
+                format!(
+                    "{} remaining",
+                    format_duration(Duration::from_secs(expires - unix_now()))
+                )
+            } else {
+                "expired".to_string()
+            }
+        ),
+        format!("CRC:         {}", crc_name(&primary.crc)),
+    ];
+    for b in &bndl.canonicals {
+        let data = match b.data() {
+            CanonicalData::HopCount(limit, count) => format!("{} of {} hops", count, limit),
+            CanonicalData::BundleAge(ms) => format_duration(Duration::from_millis(*ms)).to_string(),
+            CanonicalData::PreviousNode(eid) => eid.to_string(),
+            CanonicalData::Data(_) => payload_summary(bndl),
+            CanonicalData::Unknown(data) => format!("{} bytes", data.len()),
+            CanonicalData::DecodingError => "could not decode".to_string(),
+        };
+        lines.push(format!(
+            "{:13}{}, flags {:#x}, {}: {}",
+            format!("Block {}:", b.block_number),
+            block_name(b.block_type),
+            b.block_control_flags,
+            crc_name(&b.crc),
+            data
+        ));
+    }
+    lines
+}
@@ -1 +1,23 @@
+pub mod clock;
+pub mod export;
+pub mod ignore;
+pub mod inspect;
+pub mod mentions;
+pub mod metrics;
+pub mod multipart;
+pub mod options;
+pub mod payload;
+pub mod ping;
+pub mod profiles;
+pub mod proto;
+pub mod recent;
+pub mod roster;
+pub mod schedule;
+pub mod settings;
+pub mod spool;
+pub mod stats;
+pub mod theme;
+pub mod timefmt;
+pub mod trace;
+pub mod transcript;
 pub mod ws;
@@ -1,55 +1,80 @@
 extern crate linefeed;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use bp7::EndpointID;
 use clap::{crate_authors, crate_version, App, Arg};
-use crossbeam_channel::{unbounded, Receiver, Sender};
-use dtn7_plus::client::{DtnClient, WsSendData};
-use dtn7_plus::sms::SmsBuilder;
+use crossbeam_channel::{unbounded, Sender};
+use dtn7_plus::client::DtnClient;
 use humantime::{format_duration, parse_duration};
-use linefeed::chars::escape_sequence;
-use linefeed::command::COMMANDS;
+use linefeed::command::Command;
 use linefeed::complete::{Completer, Completion};
-use linefeed::inputrc::parse_text;
 use linefeed::terminal::Terminal;
 use linefeed::{Interface, Prompter, ReadResult};
-use std::convert::{TryFrom, TryInto};
+use std::collections::HashSet;
+use std::convert::TryInto;
 use std::io;
-use std::io::{stdout, Read, Write};
-use std::sync::Arc;
+use std::net::TcpListener;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::{collections::HashSet, time::Duration};
-use termion::color::{AnsiValue, Bg, DetectColors};
-use termion::raw::IntoRawMode;
-use termion::{clear, color::*, style};
+use std::time::Duration;
+use termion::color::AnsiValue;
+use termion::{clear, color::*};
 use ws::Builder;
 
+use dtnchat::clock::ClockSkew;
+use dtnchat::export::{self, Format};
+use dtnchat::ignore::{self, IgnoreList};
+use dtnchat::inspect;
+use dtnchat::mentions::Mentions;
+use dtnchat::metrics::Metrics;
+use dtnchat::multipart::{self, Reassembler};
+use dtnchat::ping::{self, Pings};
+use dtnchat::profiles::Profiles;
+use dtnchat::proto::{Action, Annotation, Control, Ping, Profile};
+use dtnchat::recent::Recent;
+use dtnchat::roster::{self, Roster};
+use dtnchat::schedule::{self, Schedule};
+use dtnchat::settings::{self, Encoding, Settings};
+use dtnchat::spool;
+use dtnchat::stats::{self, Stats};
+use dtnchat::theme::{self, Theme};
+use dtnchat::timefmt::{self, format_time, TimeFormat};
+use dtnchat::trace::{self, Traces};
+use dtnchat::transcript::{self, Entry, Query, Transcript};
 use dtnchat::ws::*;
 
 const HISTORY_FILE: &str = "linefeed.hst";
 
-fn print_logo() {
-    println!("{}", clear::All);
-    /*let count;
-    {
-        let mut term = stdout().into_raw_mode().unwrap();
-        count = term.available_colors().unwrap();
-    }*/
-
-    //println!("This terminal supports {} colors.", count);
-    //for i in 0..count {
-    //print!("{} {}", Bg(AnsiValue(i as u8)), Bg(AnsiValue(0)));
-    //}
-    //println!();
-
-    let logo = include_str!("../logo.txt");
-    let mut line_color = 0;
-    for line in logo.split("\n") {
-        println!("  {}{}", Fg(AnsiValue::rgb(0, line_color, 0)), line);
-        line_color = (line_color + 1) % 6;
+fn print_logo(theme: &Theme) {
+    if !theme.accessible {
+        println!("{}", clear::All);
+        /*let count;
+        {
+            let mut term = stdout().into_raw_mode().unwrap();
+            count = term.available_colors().unwrap();
+        }*/
+
+        //println!("This terminal supports {} colors.", count);
+        //for i in 0..count {
+        //print!("{} {}", Bg(AnsiValue(i as u8)), Bg(AnsiValue(0)));
+        //}
+        //println!();
+
+        let logo = include_str!("../logo.txt");
+        let mut line_color = 0;
+        for line in logo.split("\n") {
+            if theme.is_monochrome() {
+                println!("  {}", line);
+            } else {
+                println!("  {}{}", Fg(AnsiValue::rgb(0, line_color, 0)), line);
+            }
+            line_color = (line_color + 1) % 6;
+        }
+        println!("{}", theme.reset());
+        //println!("{}{}{}", Fg(LightBlue), logo, style::Reset);
     }
-    println!("{}", style::Reset);
-    //println!("{}{}{}", Fg(LightBlue), logo, style::Reset);
 
     println!("This is a simple dtn chat and messaging program.");
     println!("Enter \"/help\" for a list of commands.");
@@ -59,31 +84,7 @@ fn print_logo() {
 fn pe(c: String) -> String {
     format!("\x01{}\x02", c)
 }
-fn send_sms(
-    tx: Sender<WsCommand>,
-    src: EndpointID,
-    dst: EndpointID,
-    lifetime: Duration,
-    msg: &str,
-) -> Result<()> {
-    let sms = serde_cbor::to_vec(
-        &SmsBuilder::new()
-            .compression(true)
-            .message(msg.trim())
-            .build()?,
-    )?;
-    let data = Outgoing {
-        src,
-        dst,
-        delivery_notification: true,
-        lifetime,
-        data: sms,
-    };
-    tx.send(WsCommand::SendData(data))?;
-    Ok(())
-}
 fn main() -> Result<()> {
-    print_logo();
     let matches = App::new("dtnchat")
         .version(crate_version!())
         .author(crate_authors!())
@@ -111,8 +112,66 @@ fn main() -> Result<()> {
                 .help("Verbose output")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name("spool")
+                .short("s")
+                .long("spool")
+                .value_name("DIR")
+                .help("Also write outgoing bundles to DIR (sneakernet mode)")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("beacon")
+                .short("b")
+                .long("beacon")
+                .value_name("INTERVAL")
+                .help("Presence beacon interval for joined groups, \"off\" to disable (default = 10m)")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("nick")
+                .short("n")
+                .long("nick")
+                .value_name("NICK")
+                .help("Nickname shown to other peers (default = node name)")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("metrics")
+                .short("m")
+                .long("metrics")
+                .value_name("PORT")
+                .help("Serve Prometheus metrics on http://localhost:PORT/metrics")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("theme")
+                .long("theme")
+                .value_name("THEME")
+                .help("Colour theme: default, light, mono or accessible (default = theme in dtnchat.settings)")
+                .required(false)
+                .takes_value(true),
+        )
         .get_matches();
 
+    let mut initial_settings = Settings::default();
+    if let Err(e) = initial_settings.load() {
+        eprintln!("Could not load settings {}: {}", settings::SETTINGS_FILE, e);
+    }
+    // the command line wins over saved settings
+    if let Some(name) = matches.value_of("theme") {
+        initial_settings.set("theme", name)?;
+    }
+    if !theme::colour_enabled() {
+        initial_settings.theme = initial_settings.theme.without_colour();
+    }
+    let theme = initial_settings.theme;
+    print_logo(&theme);
+
     let port = std::env::var("DTN_WEB_PORT").unwrap_or_else(|_| "3000".into());
     let port = matches.value_of("port").unwrap_or(&port); // string is fine no need to parse number
 
@@ -122,6 +181,21 @@ fn main() -> Result<()> {
         "127.0.0.1"
     };
     let verbose = matches.is_present("verbose");
+    let spool_dir = matches.value_of("spool").map(PathBuf::from);
+    let beacon_interval = match matches.value_of("beacon") {
+        Some("off") => None,
+        Some(interval) => {
+            let interval = parse_duration(interval).expect("invalid beacon interval");
+            if interval < roster::MIN_BEACON_INTERVAL {
+                bail!(
+                    "beacon interval must be at least {}",
+                    format_duration(roster::MIN_BEACON_INTERVAL)
+                );
+            }
+            Some(interval)
+        }
+        None => Some(roster::BEACON_INTERVAL),
+    };
 
     let client = DtnClient::with_host_and_port(
         localhost.into(),
@@ -136,6 +210,60 @@ fn main() -> Result<()> {
     client.register_application_endpoint(&endpoint.to_string())?;
     let iface = interface.clone();
     let (tx, rx) = unbounded::<WsCommand>();
+    let spool_dir2 = spool_dir.clone();
+    let roster = Arc::new(Mutex::new(Roster::new()));
+    let roster2 = roster.clone();
+    let profiles = Arc::new(Mutex::new(Profiles::new()));
+    let profiles2 = profiles.clone();
+    let me = Arc::new(Mutex::new(Profile {
+        node: localnode.node().unwrap(),
+        nick: matches
+            .value_of("nick")
+            .map(String::from)
+            .unwrap_or_else(|| localnode.node().unwrap()),
+        status: None,
+    }));
+    let me2 = me.clone();
+    let mentions = Arc::new(Mutex::new(Mentions::new()));
+    let mentions2 = mentions.clone();
+    let ignored = Arc::new(Mutex::new(IgnoreList::load().unwrap_or_else(|e| {
+        eprintln!("Could not load ignore list {}: {}", ignore::IGNORE_FILE, e);
+        IgnoreList::new()
+    })));
+    let ignored2 = ignored.clone();
+    let transcript = Arc::new(Mutex::new(Transcript::load().unwrap_or_else(|e| {
+        eprintln!(
+            "Could not load transcript {}, keeping messages in memory only: {}",
+            transcript::TRANSCRIPT_FILE,
+            e
+        );
+        Transcript::new()
+    })));
+    let transcript2 = transcript.clone();
+    initial_settings.verbose |= verbose;
+    let settings = Arc::new(Mutex::new(initial_settings));
+    let settings2 = settings.clone();
+    let skew = Arc::new(Mutex::new(ClockSkew::new()));
+    let skew2 = skew.clone();
+    let pings = Arc::new(Mutex::new(Pings::new()));
+    let pings2 = pings.clone();
+    let tx_echo = tx.clone();
+    let traces = Arc::new(Mutex::new(Traces::new()));
+    let traces2 = traces.clone();
+    let recent = Arc::new(Mutex::new(Recent::new()));
+    let recent2 = recent.clone();
+    let stats = if settings.lock().unwrap().persist_stats {
+        Stats::load().unwrap_or_else(|e| {
+            eprintln!("Could not load statistics {}: {}", stats::STATS_FILE, e);
+            Stats::new()
+        })
+    } else {
+        Stats::new()
+    };
+    let stats = Arc::new(Mutex::new(stats));
+    let stats2 = stats.clone();
+    let connected = Arc::new(Mutex::new(false));
+    let connected2 = connected.clone();
     //let thread_rx = rx.clone();
     //let mut ws = new_chat_connection(rx.clone(), iface.clone(), verbose, endpoint.clone());
 
@@ -144,17 +272,45 @@ fn main() -> Result<()> {
             let out2 = out.clone();
             let rx2 = rx.clone();
             let iface2 = iface.clone();
+            let spool_dir3 = spool_dir2.clone();
+            let transcript3 = transcript2.clone();
+            let recent3 = recent2.clone();
+            let settings3 = settings2.clone();
+            let stats3 = stats2.clone();
             thread::spawn(move || {
-                send_listener(rx2.clone(), out2.clone(), iface2.clone(), verbose)
+                send_listener(
+                    rx2.clone(),
+                    out2.clone(),
+                    Some(iface2.clone()),
+                    spool_dir3,
+                    transcript3,
+                    recent3,
+                    settings3,
+                    stats3,
+                )
             });
 
             ChatConnection {
                 localnode: endpoint2.clone(),
                 out,
                 subscribed: false,
-                verbose,
                 iface: iface.clone(),
                 recv: rx.clone(),
+                roster: roster2.clone(),
+                profiles: profiles2.clone(),
+                me: me2.clone(),
+                mentions: mentions2.clone(),
+                ignored: ignored2.clone(),
+                transcript: transcript2.clone(),
+                parts: Arc::new(Mutex::new(Reassembler::new())),
+                recent: recent2.clone(),
+                settings: settings2.clone(),
+                skew: skew2.clone(),
+                pings: pings2.clone(),
+                traces: traces2.clone(),
+                stats: stats2.clone(),
+                connected: connected2.clone(),
+                tx: tx_echo.clone(),
             }
         })
         .unwrap();
@@ -167,14 +323,20 @@ fn main() -> Result<()> {
         eids: peers.clone(),
     });
     interface.set_completer(completer);
-    interface.set_prompt(&format!(
-        "{}{} {}> {}",
-        pe(Fg(LightBlue).to_string()),
-        localnode.node().unwrap(),
-        pe(Fg(LightWhite).to_string()),
-        pe(termion::style::Reset.to_string())
+    interface.set_prompt(&make_prompt(
+        &localnode,
+        &query,
+        &settings.lock().unwrap().theme,
     ))?;
 
+    // Alt-Enter inserts a newline instead of sending the message
+    interface.define_function(
+        "insert-newline",
+        Arc::new(|prompter: &mut Prompter<_>, _count: i32, _ch: char| prompter.insert_str("\n")),
+    );
+    interface.bind_sequence("\x1b\r", Command::from_str("insert-newline"));
+    interface.bind_sequence("\x1b\n", Command::from_str("insert-newline"));
+
     if let Err(e) = interface.load_history(HISTORY_FILE) {
         if e.kind() == io::ErrorKind::NotFound {
             println!(
@@ -192,7 +354,39 @@ fn main() -> Result<()> {
     });
 
     let mut groups: HashSet<String> = HashSet::new();
-    let mut lifetime: Duration = Duration::from_secs(60 * 60);
+    let (groups_tx, groups_rx) = unbounded::<HashSet<String>>();
+    if let Some(interval) = beacon_interval {
+        let tx2 = tx.clone();
+        let src = endpoint.clone();
+        let me3 = me.clone();
+        thread::spawn(move || roster::beacon_loop(groups_rx, tx2, src, me3, interval));
+    }
+    let schedule = Arc::new(Mutex::new(Schedule::load().unwrap_or_else(|e| {
+        eprintln!("Could not load schedule {}: {}", schedule::SCHEDULE_FILE, e);
+        Schedule::new()
+    })));
+    {
+        let schedule2 = schedule.clone();
+        let tx2 = tx.clone();
+        let src = endpoint.clone();
+        let settings3 = settings.clone();
+        thread::spawn(move || schedule::schedule_loop(schedule2, tx2, src, settings3));
+    }
+    {
+        let stats2 = stats.clone();
+        let settings3 = settings.clone();
+        thread::spawn(move || stats::save_loop(stats2, settings3));
+    }
+    if let Some(metrics_port) = matches.value_of("metrics") {
+        let listener = TcpListener::bind(format!("{}:{}", localhost, metrics_port))?;
+        let metrics = Metrics {
+            stats: stats.clone(),
+            connected,
+            queue: tx.clone(),
+            schedule: schedule.clone(),
+        };
+        thread::spawn(move || metrics.serve(listener));
+    }
 
     while let ReadResult::Input(line) = interface.read_line()? {
         if !line.trim().is_empty() {
@@ -210,41 +404,121 @@ fn main() -> Result<()> {
                 println!();
             }
             "/lifetime" => {
-                println!("Current bundle lifetime: {}", format_duration(lifetime));
+                let mut settings = settings.lock().unwrap();
+                println!(
+                    "Current bundle lifetime: {}",
+                    format_duration(settings.lifetime)
+                );
                 if args.len() > 1 {
                     if let Ok(new_lifetime) = parse_duration(args) {
-                        lifetime = new_lifetime;
-                        println!("New bundle lifetime: {}", format_duration(lifetime));
+                        settings.lifetime = new_lifetime;
+                        println!(
+                            "New bundle lifetime: {}",
+                            format_duration(settings.lifetime)
+                        );
                     } else {
                         println!("Invalid lifetime duration format!");
                     }
                 }
             }
-            "/join" => {
-                let dst: EndpointID = if let Ok(dst_num) = args.parse::<u64>() {
-                    format!("ipn://{}.767", dst_num).try_into()?
+            "/partsize" => set_setting(&settings, "part-size", args),
+            "/set" => {
+                let (name, value) = split_first_word(args);
+                if name.is_empty() || value.is_empty() {
+                    println!("Usage: /set <name> <value>");
+                } else if settings::NAMES.contains(&name) {
+                    set_setting(&settings, name, value);
+                    if name == "theme" {
+                        interface.set_prompt(&make_prompt(
+                            &localnode,
+                            &query,
+                            &settings.lock().unwrap().theme,
+                        ))?;
+                    }
+                } else if interface.set_variable(name, value).is_some() {
+                    // line editor variables like bell-style
+                    println!("{} = {}", name, value);
                 } else {
-                    format!("dtn://{}/~sms", args).try_into()?
+                    println!("Unknown setting {}", name);
+                }
+            }
+            "/get" => {
+                let settings = settings.lock().unwrap();
+                if args.is_empty() {
+                    for name in settings::NAMES {
+                        println!("  {:24} {}", name, settings.get(name).unwrap_or_default());
+                    }
+                } else if let Some(value) = settings.get(args) {
+                    println!("{} = {}", args, value);
+                } else if let Some(value) = interface.get_variable(args) {
+                    println!("{} = {}", args, value);
+                } else {
+                    println!("Unknown setting {}", args);
+                }
+            }
+            "/save-settings" => match settings.lock().unwrap().save() {
+                Ok(()) => println!("Settings saved to {}", settings::SETTINGS_FILE),
+                Err(e) => eprintln!("Could not save settings {}: {}", settings::SETTINGS_FILE, e),
+            },
+            "/timefmt" => set_setting(&settings, "time-format", args),
+            "/theme" => {
+                if args.is_empty() {
+                    println!("Available themes: {}", theme::THEMES.join(", "));
+                } else {
+                    set_setting(&settings, "theme", args);
+                    interface.set_prompt(&make_prompt(
+                        &localnode,
+                        &query,
+                        &settings.lock().unwrap().theme,
+                    ))?;
+                }
+            }
+            "/delay" => set_setting(&settings, "show-delay", args),
+            "/join" => {
+                let dst = match roster::chat_eid(args, true) {
+                    Ok(dst) => dst,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
                 };
-                client.register_application_endpoint(&dst.to_string())?;
-                if peers.insert(dst.node().unwrap()) {
+                if let Err(e) = client.register_application_endpoint(&dst.to_string()) {
+                    println!("{}", e);
+                    continue;
+                }
+                let node = dst.node().unwrap();
+                groups.insert(node.clone());
+                if peers.insert(node.clone()) {
                     let completer = Arc::new(DtnChatCompleter {
                         eids: peers.clone(),
                     });
                     interface.set_completer(completer);
+                    // first contact, tell the group who we are
+                    send_profile(
+                        &tx,
+                        &endpoint,
+                        &[node],
+                        &groups,
+                        &settings.lock().unwrap(),
+                        &me.lock().unwrap(),
+                    )?;
                 }
-                groups.insert(dst.node().unwrap());
                 tx.send(WsCommand::Text(format!("/subscribe {}", dst)))?;
+                groups_tx.send(groups.clone()).ok();
             }
             "/leave" => {
                 if args != localnode.node().unwrap() {
-                    let dst: EndpointID = if let Ok(dst_num) = args.parse::<u64>() {
-                        format!("ipn://{}.767", dst_num)
-                    } else {
-                        format!("dtn://{}/~sms", args)
+                    let dst = match roster::chat_eid(args, true) {
+                        Ok(dst) => dst,
+                        Err(e) => {
+                            println!("{}", e);
+                            continue;
+                        }
+                    };
+                    if let Err(e) = client.unregister_application_endpoint(&dst.to_string()) {
+                        println!("{}", e);
+                        continue;
                     }
-                    .try_into()?;
-                    client.unregister_application_endpoint(&dst.to_string())?;
                     if peers.remove(&dst.node().unwrap()) {
                         let completer = Arc::new(DtnChatCompleter {
                             eids: peers.clone(),
@@ -253,6 +527,7 @@ fn main() -> Result<()> {
                     }
                     groups.remove(&dst.node().unwrap());
                     tx.send(WsCommand::Text(format!("/unsubscribe {}", dst)))?;
+                    groups_tx.send(groups.clone()).ok();
                 }
             }
             "/list" => {
@@ -262,69 +537,620 @@ fn main() -> Result<()> {
                 }
                 println!();
             }
+            "/who" => {
+                let group = if !args.is_empty() {
+                    Some(args.to_string())
+                } else {
+                    query
+                        .as_ref()
+                        .and_then(|q| q.node())
+                        .filter(|q| groups.contains(q))
+                };
+                if let Some(group) = group {
+                    let members = roster.lock().unwrap().members(&group);
+                    println!("members of {}:", group);
+                    let time_format = settings.lock().unwrap().time_format;
+                    for m in members {
+                        println!(
+                            "  {:15} ({}) last seen {} ({})",
+                            m.nick,
+                            m.node,
+                            format_time(m.last_seen, time_format),
+                            timefmt::ago(m.last_seen)
+                        );
+                    }
+                    println!();
+                } else {
+                    println!("Usage: /who <group>");
+                }
+            }
             "/query" => {
                 if args.is_empty() {
                     query = None;
-                    interface.set_prompt(&format!(
-                        "{}{} {}> {}",
-                        pe(Fg(LightBlue).to_string()),
-                        localnode.node().unwrap(),
-                        pe(Fg(LightWhite).to_string()),
-                        pe(termion::style::Reset.to_string())
+                    interface.set_prompt(&make_prompt(
+                        &localnode,
+                        &query,
+                        &settings.lock().unwrap().theme,
                     ))?;
                 } else {
                     let (dst_node, _msg) = split_first_word(args);
-                    let dst: EndpointID = if let Ok(dst_num) = dst_node.parse::<u64>() {
-                        format!("ipn://{}.767", dst_num)
-                    } else if groups.contains(dst_node) {
-                        format!("dtn://{}/~sms", dst_node)
-                    } else {
-                        format!("dtn://{}/sms", dst_node)
-                    }
-                    .try_into()?;
-                    if !peers.contains(&dst.node().unwrap()) {
-                        peers.insert(dst.node().unwrap());
+                    let (dst, node) = match peer_eid(dst_node, &groups) {
+                        Ok(peer) => peer,
+                        Err(e) => {
+                            println!("{}", e);
+                            continue;
+                        }
+                    };
+                    if peers.insert(node.clone()) {
                         let completer = Arc::new(DtnChatCompleter {
                             eids: peers.clone(),
                         });
                         interface.set_completer(completer);
+                        // first contact, the peer learns our nick and status
+                        send_profile(
+                            &tx,
+                            &endpoint,
+                            &[node],
+                            &groups,
+                            &settings.lock().unwrap(),
+                            &me.lock().unwrap(),
+                        )?;
                     }
                     query = Some(dst);
-                    interface.set_prompt(&format!(
-                        "{}{} {}>> {}{} {}> {}",
-                        pe(Fg(LightBlue).to_string()),
-                        localnode.node().unwrap(),
-                        pe(Fg(LightWhite).to_string()),
-                        pe(Fg(LightCyan).to_string()),
-                        query.clone().unwrap().node().unwrap(),
-                        pe(Fg(LightWhite).to_string()),
-                        pe(termion::style::Reset.to_string())
+                    interface.set_prompt(&make_prompt(
+                        &localnode,
+                        &query,
+                        &settings.lock().unwrap().theme,
                     ))?;
                 }
             }
             "/msg" => {
                 //println!("msg: {}", args);
                 let (dst_node, msg) = split_first_word(args);
-                let dst: EndpointID = if let Ok(dst_num) = dst_node.parse::<u64>() {
-                    format!("ipn://{}.767", dst_num).try_into()?
-                } else if groups.contains(dst_node) {
-                    format!("dtn://{}/~sms", dst_node).try_into()?
-                } else {
-                    format!("dtn://{}/sms", dst_node).try_into()?
+                let (dst, node) = match peer_eid(dst_node, &groups) {
+                    Ok(peer) => peer,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
                 };
-                if !peers.contains(&dst.node().unwrap()) {
-                    peers.insert(dst.node().unwrap());
+                if peers.insert(node.clone()) {
                     let completer = Arc::new(DtnChatCompleter {
                         eids: peers.clone(),
                     });
                     interface.set_completer(completer);
+                    // first contact, the peer learns our nick and status
+                    send_profile(
+                        &tx,
+                        &endpoint,
+                        &[node],
+                        &groups,
+                        &settings.lock().unwrap(),
+                        &me.lock().unwrap(),
+                    )?;
+                }
+                if let Err(e) = send_sms(
+                    tx.clone(),
+                    endpoint.clone(),
+                    dst,
+                    &settings.lock().unwrap(),
+                    msg,
+                ) {
+                    println!("{}", e);
+                }
+            }
+            "/with" => {
+                // leading name=value words are options for this message only
+                let mut rest = args;
+                let mut overrides = Vec::new();
+                loop {
+                    let (word, tail) = split_first_word(rest);
+                    match word.split_once('=') {
+                        Some(option) => overrides.push(option),
+                        None => break,
+                    }
+                    rest = tail;
+                }
+                let (dst_node, msg) = split_first_word(rest);
+                if overrides.is_empty() || dst_node.is_empty() || msg.is_empty() {
+                    println!("Usage: /with <option=value>... <peer> <message>");
+                } else {
+                    let (dst, node) = match peer_eid(dst_node, &groups) {
+                        Ok(peer) => peer,
+                        Err(e) => {
+                            println!("{}", e);
+                            continue;
+                        }
+                    };
+                    let mut settings = settings.lock().unwrap().clone();
+                    let mut options = settings.options_for(&dst);
+                    match overrides
+                        .iter()
+                        .try_for_each(|(name, value)| options.set(name, value))
+                    {
+                        Ok(()) => {
+                            settings.conversations.insert(node, options);
+                            if let Err(e) =
+                                send_sms(tx.clone(), endpoint.clone(), dst, &settings, msg)
+                            {
+                                println!("{}", e);
+                            }
+                        }
+                        Err(e) => println!("{}", e),
+                    }
+                }
+            }
+            "/encoding" => {
+                let words: Vec<&str> = args.split_whitespace().collect();
+                let mut settings = settings.lock().unwrap();
+                match words.as_slice() {
+                    [] => {
+                        for (peer, encoding) in &settings.encodings {
+                            println!("  {}: {}", peer, encoding);
+                        }
+                        println!("all other conversations use {}", Encoding::Sms);
+                    }
+                    [peer] => match peer_eid(peer, &groups) {
+                        Ok((dst, _)) => println!("{} uses {}", peer, settings.encoding_for(&dst)),
+                        Err(e) => println!("{}", e),
+                    },
+                    [peer, encoding] => {
+                        match (encoding.parse::<Encoding>(), peer_eid(peer, &groups)) {
+                            (Ok(encoding), Ok((dst, node))) => {
+                                // full endpoints are kept as typed, others apply to the whole node
+                                let key = if peer.contains(':') {
+                                    dst.to_string()
+                                } else {
+                                    node
+                                };
+                                if encoding == Encoding::Sms {
+                                    settings.encodings.remove(&key);
+                                } else {
+                                    settings.encodings.insert(key, encoding);
+                                }
+                                println!("{} uses {}", peer, encoding);
+                            }
+                            (Err(e), _) | (_, Err(e)) => println!("{}", e),
+                        }
+                    }
+                    _ => println!("Usage: /encoding [peer] [sms|plain]"),
+                }
+            }
+            "/listen" => {
+                if args.is_empty() {
+                    println!("Usage: /listen <endpoint>");
+                } else {
+                    let dst: EndpointID = match args.try_into() {
+                        Ok(dst) => dst,
+                        Err(e) => {
+                            println!("{}", e);
+                            continue;
+                        }
+                    };
+                    if let Err(e) = client.register_application_endpoint(&dst.to_string()) {
+                        println!("{}", e);
+                        continue;
+                    }
+                    tx.send(WsCommand::Text(format!("/subscribe {}", dst)))?;
+                }
+            }
+            "/unlisten" => {
+                if args.is_empty() {
+                    println!("Usage: /unlisten <endpoint>");
+                } else {
+                    let dst: EndpointID = match args.try_into() {
+                        Ok(dst) => dst,
+                        Err(e) => {
+                            println!("{}", e);
+                            continue;
+                        }
+                    };
+                    if let Err(e) = client.unregister_application_endpoint(&dst.to_string()) {
+                        println!("{}", e);
+                        continue;
+                    }
+                    tx.send(WsCommand::Text(format!("/unsubscribe {}", dst)))?;
+                }
+            }
+            "/bundle" => {
+                let words: Vec<&str> = args.split_whitespace().collect();
+                let mut settings = settings.lock().unwrap();
+                let result = match words.as_slice() {
+                    [] => {
+                        println!("bundle options: {}", settings.bundle);
+                        for (peer, options) in &settings.conversations {
+                            println!("  {}: {}", peer, options);
+                        }
+                        Ok(())
+                    }
+                    [peer, "reset"] => {
+                        if settings.conversations.remove(*peer).is_some() {
+                            println!("{} uses the default bundle options again", peer);
+                        }
+                        Ok(())
+                    }
+                    [name, value] => settings.bundle.set(name, value),
+                    [peer, name, value] => peer_eid(peer, &groups).and_then(|(dst, node)| {
+                        let mut options = settings.options_for(&dst);
+                        options.set(name, value)?;
+                        settings.conversations.insert(node, options);
+                        Ok(())
+                    }),
+                    _ => {
+                        println!("Usage: /bundle [peer] [<option> <value>|reset]");
+                        Ok(())
+                    }
+                };
+                if let Err(e) = result {
+                    println!("{}", e);
+                }
+            }
+            "/at" => {
+                let (time, rest) = split_first_word(args);
+                // absolute times may contain a space between date and time
+                let (clock, rest2) = split_first_word(rest);
+                let (time, rest) =
+                    if schedule::parse_time(time).is_err() && schedule::is_clock_time(clock) {
+                        (format!("{} {}", time, clock), rest2)
+                    } else {
+                        (time.to_string(), rest)
+                    };
+                let (dst_node, msg) = split_first_word(rest);
+                if dst_node.is_empty() || msg.is_empty() {
+                    println!("Usage: /at <time|+duration> <peer> <message>");
+                } else {
+                    match schedule::parse_time(&time) {
+                        Ok(at) => {
+                            let (dst, _) = match peer_eid(dst_node, &groups) {
+                                Ok(peer) => peer,
+                                Err(e) => {
+                                    println!("{}", e);
+                                    continue;
+                                }
+                            };
+                            let added = schedule.lock().unwrap().add(at, &dst, msg);
+                            match added {
+                                Ok(id) => println!(
+                                    "Scheduled message {} to {} at {}",
+                                    id,
+                                    dst,
+                                    format_time(at, settings.lock().unwrap().time_format)
+                                ),
+                                Err(e) => println!("Could not save schedule: {}", e),
+                            }
+                        }
+                        Err(e) => println!("{}", e),
+                    }
+                }
+            }
+            "/schedules" => {
+                let time_format = settings.lock().unwrap().time_format;
+                println!("scheduled messages:");
+                for i in schedule.lock().unwrap().items() {
+                    println!(
+                        "  {:3} {} {} {}",
+                        i.id,
+                        format_time(i.at, time_format),
+                        i.dst,
+                        i.msg
+                    );
+                }
+                println!();
+            }
+            "/unschedule" => {
+                match args
+                    .parse::<u64>()
+                    .map(|id| schedule.lock().unwrap().remove(id))
+                {
+                    Ok(Ok(true)) => println!("Removed scheduled message {}", args),
+                    Ok(Ok(false)) => println!("No scheduled message {}", args),
+                    Ok(Err(e)) => println!("Could not save schedule: {}", e),
+                    Err(_) => println!("Usage: /unschedule <id>"),
                 }
-                send_sms(tx.clone(), endpoint.clone(), dst, lifetime, msg)?;
             }
             "/peers" => {
                 println!("known peers: {}", args);
+                let profiles = profiles.lock().unwrap();
                 for i in peers.iter() {
-                    println!("  {}", i);
+                    println!("  {}", profiles.display(i));
+                }
+            }
+            "/nick" => {
+                if args.is_empty() {
+                    println!("Current nickname: {}", me.lock().unwrap().nick);
+                } else {
+                    me.lock().unwrap().nick = args.to_string();
+                    println!("New nickname: {}", args);
+                    send_profile(
+                        &tx,
+                        &endpoint,
+                        &peers,
+                        &groups,
+                        &settings.lock().unwrap(),
+                        &me.lock().unwrap(),
+                    )?;
+                }
+            }
+            "/status" => {
+                me.lock().unwrap().status = if args.is_empty() {
+                    println!("Status cleared");
+                    None
+                } else {
+                    println!("New status: {}", args);
+                    Some(args.to_string())
+                };
+                send_profile(
+                    &tx,
+                    &endpoint,
+                    &peers,
+                    &groups,
+                    &settings.lock().unwrap(),
+                    &me.lock().unwrap(),
+                )?;
+            }
+            "/highlight" => {
+                let mut mentions = mentions.lock().unwrap();
+                if let Some(keyword) = args.strip_prefix('-') {
+                    if mentions.remove_keyword(keyword) {
+                        println!("No longer highlighting {}", keyword);
+                    }
+                } else if !args.is_empty() {
+                    mentions.add_keyword(args);
+                }
+                println!("highlighted keywords:");
+                for k in mentions.keywords() {
+                    println!("  {}", k);
+                }
+            }
+            "/mentions" => {
+                let time_format = settings.lock().unwrap().time_format;
+                println!("recent mentions:");
+                for m in mentions.lock().unwrap().hits() {
+                    println!(
+                        "  [{} {} > {}] {}",
+                        format_time(m.time, time_format),
+                        m.src,
+                        m.dst,
+                        m.msg
+                    );
+                }
+                println!();
+            }
+            "/ignore" => {
+                let mut ignored = ignored.lock().unwrap();
+                if !args.is_empty() && ignored.add(args) {
+                    if let Err(e) = ignored.save() {
+                        eprintln!("Could not save ignore list {}: {}", ignore::IGNORE_FILE, e);
+                    }
+                }
+                println!("ignored senders and groups:");
+                for p in ignored.patterns() {
+                    println!("  {}", p);
+                }
+            }
+            "/unignore" => {
+                let mut ignored = ignored.lock().unwrap();
+                if args.is_empty() {
+                    println!("Usage: /unignore <eid-or-pattern>");
+                } else if ignored.remove(args) {
+                    println!("No longer ignoring {}", args);
+                    if let Err(e) = ignored.save() {
+                        eprintln!("Could not save ignore list {}: {}", ignore::IGNORE_FILE, e);
+                    }
+                } else {
+                    println!("{} is not ignored", args);
+                }
+            }
+            "/ratelimit" => {
+                let mut ignored = ignored.lock().unwrap();
+                if !args.is_empty() {
+                    match args.parse::<usize>() {
+                        Ok(0) => ignored.rate_limit = None,
+                        Ok(limit) => ignored.rate_limit = Some(limit),
+                        Err(_) => println!("Invalid rate limit!"),
+                    }
+                }
+                match ignored.rate_limit {
+                    Some(limit) => println!("Muting senders above {} messages per minute", limit),
+                    None => println!("No rate limit"),
+                }
+            }
+            "/search" => match parse_search(args) {
+                Ok(query) => {
+                    let transcript = transcript.lock().unwrap();
+                    let hits = transcript.search(&query);
+                    let (time_format, theme) = {
+                        let settings = settings.lock().unwrap();
+                        (settings.time_format, settings.theme)
+                    };
+                    for e in &hits {
+                        print_entry(e, time_format, &theme);
+                    }
+                    println!("{} matches", hits.len());
+                }
+                Err(e) => println!("{}", e),
+            },
+            "/export" => {
+                let mut words = args.split_whitespace();
+                match (words.next(), words.next(), words.next()) {
+                    (Some(peer), Some(format), Some(path)) => match format.parse::<Format>() {
+                        Ok(format) => {
+                            let transcript = transcript.lock().unwrap();
+                            let entries = transcript.conversation(peer);
+                            match export::export(peer, &entries, format, Path::new(path)) {
+                                Ok(()) => {
+                                    println!("Exported {} messages to {}", entries.len(), path)
+                                }
+                                Err(e) => println!("Export failed: {}", e),
+                            }
+                        }
+                        Err(e) => println!("{}", e),
+                    },
+                    _ => println!("Usage: /export <peer|group|all> <json|html|txt> <path>"),
+                }
+            }
+            "/ping" => {
+                let (dst_node, count) = split_first_word(args);
+                if dst_node.is_empty() {
+                    println!("pings:");
+                    for s in pings.lock().unwrap().sessions() {
+                        print!(
+                            "  {}: {} sent, {} received, {:.0}% loss",
+                            s.peer,
+                            s.sent(),
+                            s.answered.len(),
+                            s.loss()
+                        );
+                        match s.rtt_stats() {
+                            Some((min, avg, max)) => println!(
+                                ", rtt min/avg/max {}/{}/{}",
+                                format_duration(Duration::from_millis(min)),
+                                format_duration(Duration::from_millis(avg)),
+                                format_duration(Duration::from_millis(max))
+                            ),
+                            None => println!(),
+                        }
+                    }
+                } else {
+                    let count = if count.is_empty() {
+                        Ok(1)
+                    } else {
+                        count.parse::<u32>()
+                    };
+                    match count {
+                        Ok(count) if count > 0 => {
+                            let (dst, _) = match peer_eid(dst_node, &groups) {
+                                Ok(peer) => peer,
+                                Err(e) => {
+                                    println!("{}", e);
+                                    continue;
+                                }
+                            };
+                            let id = pings.lock().unwrap().start(dst_node, count);
+                            let lifetime = settings.lock().unwrap().lifetime;
+                            let (pings2, tx2, src) = (pings.clone(), tx.clone(), endpoint.clone());
+                            thread::spawn(move || {
+                                ping::ping_loop(pings2, id, count, tx2, src, dst, lifetime)
+                            });
+                            println!("Pinging {} with {} probes", dst_node, count);
+                        }
+                        _ => println!("Usage: /ping [peer] [count]"),
+                    }
+                }
+            }
+            "/stats" => {
+                let mut stats = stats.lock().unwrap();
+                match args {
+                    "" => {
+                        println!(
+                            "Statistics since {}:",
+                            format_time(stats.since, settings.lock().unwrap().time_format)
+                        );
+                        for line in stats.total.describe() {
+                            println!("  {}", line);
+                        }
+                        for (peer, c) in &stats.peers {
+                            println!(
+                                "  {}: {} sent, {} received, {} of {} delivered",
+                                peer,
+                                c.messages_sent,
+                                c.messages_received,
+                                c.delivered,
+                                c.reports_requested
+                            );
+                        }
+                    }
+                    "reset" => {
+                        stats.reset();
+                        println!("Statistics reset");
+                    }
+                    peer => {
+                        let node = match peer_eid(peer, &groups) {
+                            Ok((_, node)) => node,
+                            Err(e) => {
+                                println!("{}", e);
+                                continue;
+                            }
+                        };
+                        match stats.peers.get(&node) {
+                            Some(c) => {
+                                println!("Statistics for {}:", node);
+                                for line in c.describe() {
+                                    println!("  {}", line);
+                                }
+                            }
+                            None => println!("No traffic with {}", node),
+                        }
+                    }
+                }
+            }
+            "/trace" => {
+                if args.is_empty() {
+                    let time_format = settings.lock().unwrap().time_format;
+                    for t in traces.lock().unwrap().traces() {
+                        println!(
+                            "trace to {} at {}:",
+                            t.peer,
+                            format_time(t.started, time_format)
+                        );
+                        for (i, hop) in t.hops.iter().enumerate() {
+                            let time = hop.received.or(hop.forwarded).or(hop.delivered);
+                            println!(
+                                "  {:2} {:20} {}{}",
+                                i + 1,
+                                hop.node,
+                                time.map(|t| format_time(t, time_format))
+                                    .unwrap_or_default(),
+                                if hop.delivered.is_some() {
+                                    " (destination)"
+                                } else {
+                                    ""
+                                }
+                            );
+                        }
+                    }
+                } else {
+                    let (dst, _) = match peer_eid(args, &groups) {
+                        Ok(peer) => peer,
+                        Err(e) => {
+                            println!("{}", e);
+                            continue;
+                        }
+                    };
+                    let id = pings.lock().unwrap().start(args, 1);
+                    let sent = ping::unix_now_ms();
+                    pings.lock().unwrap().sent(&id, 1, sent);
+                    let probe = Control::Ping(Ping {
+                        id,
+                        seq: 1,
+                        sent,
+                        ..Default::default()
+                    });
+                    let lifetime = settings.lock().unwrap().lifetime;
+                    let bndl =
+                        trace::trace_bundle(endpoint.clone(), dst, lifetime, probe.to_cbor());
+                    traces.lock().unwrap().start(&bndl.id(), args);
+                    tx.send(WsCommand::Inject(bndl))?;
+                    println!(
+                        "Tracing path to {}, reports arrive as nodes process the probe",
+                        args
+                    );
+                }
+            }
+            "/whois" => {
+                if args.is_empty() {
+                    println!("Usage: /whois <peer>");
+                } else if let Some(p) = profiles.lock().unwrap().get(args) {
+                    println!("  node:   {}", p.node);
+                    println!("  nick:   {}", p.nick);
+                    if let Some(status) = &p.status {
+                        println!("  status: {}", status);
+                    }
+                    if let Some(skew) = skew.lock().unwrap().get(&p.node) {
+                        println!("  clock:  {:+}s", skew);
+                    }
+                } else {
+                    println!("No profile known for {}", args);
                 }
             }
             "/history" => {
@@ -341,6 +1167,224 @@ fn main() -> Result<()> {
                     println!("History saved to {}", HISTORY_FILE);
                 }
             }
+            "/sneakernet" => {
+                if args == "off" {
+                    tx.send(WsCommand::Spool(None))?;
+                    println!("Sneakernet mode disabled");
+                } else if !args.is_empty() {
+                    tx.send(WsCommand::Spool(Some(PathBuf::from(args))))?;
+                    println!("Spooling outgoing bundles to {}", args);
+                } else {
+                    println!("Usage: /sneakernet <dir|off>");
+                }
+            }
+            "/import" => {
+                if args.is_empty() {
+                    println!("Usage: /import <dir>");
+                } else if let Err(e) = import_bundles(&tx, Path::new(args)) {
+                    println!("Import failed: {}", e);
+                }
+            }
+            "/recent" => {
+                let count = args.parse::<usize>().unwrap_or(10);
+                let (time_format, theme) = {
+                    let settings = settings.lock().unwrap();
+                    (settings.time_format, settings.theme)
+                };
+                let transcript = transcript.lock().unwrap();
+                for item in recent.lock().unwrap().last(count) {
+                    if let Some(e) = item.bid.as_ref().and_then(|bid| transcript.get(bid)) {
+                        let msg = if e.retracted {
+                            "[retracted]".to_string()
+                        } else if e.edited {
+                            format!("{} (edited)", e.msg)
+                        } else {
+                            e.msg.clone()
+                        };
+                        println!(
+                            "{}#{:<4}{}",
+                            theme.dim,
+                            item.n,
+                            theme.message(
+                                &format_time(e.time, time_format),
+                                &e.src,
+                                &e.src,
+                                Some(&e.dst),
+                                &msg
+                            )
+                        );
+                    }
+                }
+            }
+            "/info" => {
+                let item = args
+                    .trim_start_matches('#')
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|n| recent.lock().unwrap().get(n).cloned());
+                match item {
+                    Some(item) => {
+                        let time_format = settings.lock().unwrap().time_format;
+                        for line in inspect::describe(&item.bundle, time_format) {
+                            println!("  {}", line);
+                        }
+                        println!("  Received:    {}", format_time(item.received, time_format));
+                        let transcript = transcript.lock().unwrap();
+                        if let Some(e) = item.bid.as_ref().and_then(|bid| transcript.get(bid)) {
+                            let state = if e.retracted {
+                                "retracted"
+                            } else if e.edited {
+                                "edited"
+                            } else {
+                                "unchanged"
+                            };
+                            println!("  Message:     {}", state);
+                        }
+                    }
+                    None => println!("Usage: /info <n>, see /recent for numbers"),
+                }
+            }
+            "/save" => {
+                let (n, path) = split_first_word(args);
+                let item = n
+                    .trim_start_matches('#')
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|n| recent.lock().unwrap().get(n).cloned());
+                match item {
+                    Some(item) if !path.is_empty() => {
+                        let data = item.bundle.payload().cloned().unwrap_or_default();
+                        match std::fs::write(path, &data) {
+                            Ok(()) => println!("Saved {} bytes to {}", data.len(), path),
+                            Err(e) => println!("Could not write {}: {}", path, e),
+                        }
+                    }
+                    _ => println!("Usage: /save <n> <path>, see /recent for numbers"),
+                }
+            }
+            "/reply" => {
+                let (n, msg) = split_first_word(args);
+                let quoted = recent_entry(&recent.lock().unwrap(), &transcript.lock().unwrap(), n);
+                match quoted {
+                    Some(quoted) if quoted.retracted => println!("That message was retracted"),
+                    Some(quoted) if !msg.is_empty() => {
+                        let dst = match peer_eid(
+                            conversation_peer(&quoted, &localnode.node().unwrap()),
+                            &groups,
+                        ) {
+                            Ok((dst, _)) => dst,
+                            Err(e) => {
+                                println!("{}", e);
+                                continue;
+                            }
+                        };
+                        if let Err(e) = send_reply(
+                            tx.clone(),
+                            endpoint.clone(),
+                            dst,
+                            &settings.lock().unwrap(),
+                            &quoted,
+                            msg,
+                        ) {
+                            println!("{}", e);
+                        }
+                    }
+                    _ => println!("Usage: /reply <n> <message>, see /recent for numbers"),
+                }
+            }
+            "/react" | "/amend" | "/retract" => {
+                let (n, text) = split_first_word(args);
+                let target = recent_entry(&recent.lock().unwrap(), &transcript.lock().unwrap(), n);
+                let action = match cmd {
+                    "/react" if !text.is_empty() => Some(Action::React(text.to_string())),
+                    "/amend" if !text.is_empty() => Some(Action::Amend(text.to_string())),
+                    "/retract" => Some(Action::Retract),
+                    _ => None,
+                };
+                match (target, action) {
+                    (Some(target), Some(action)) => {
+                        if !matches!(action, Action::React(_)) && !target.outgoing {
+                            println!("Only your own messages can be changed");
+                        } else {
+                            let dst = match peer_eid(
+                                conversation_peer(&target, &localnode.node().unwrap()),
+                                &groups,
+                            ) {
+                                Ok((dst, _)) => dst,
+                                Err(e) => {
+                                    println!("{}", e);
+                                    continue;
+                                }
+                            };
+                            let annotation = Annotation {
+                                target: target.bid.clone(),
+                                action,
+                            };
+                            if let Err(e) = send_annotation(
+                                tx.clone(),
+                                endpoint.clone(),
+                                dst,
+                                &settings.lock().unwrap(),
+                                annotation,
+                            ) {
+                                println!("{}", e);
+                            }
+                        }
+                    }
+                    _ => println!(
+                        "Usage: {} <n> {}",
+                        cmd,
+                        match cmd {
+                            "/react" => "<reaction>",
+                            "/amend" => "<new text>",
+                            _ => "",
+                        }
+                    ),
+                }
+            }
+            "/compose" | "/edit" => {
+                let dst = if args.is_empty() {
+                    query.clone()
+                } else {
+                    match peer_eid(args, &groups) {
+                        Ok((dst, _)) => Some(dst),
+                        Err(e) => {
+                            println!("{}", e);
+                            continue;
+                        }
+                    }
+                };
+                if let Some(dst) = dst {
+                    let text = if cmd == "/compose" {
+                        compose(&interface)
+                    } else {
+                        edit_message().map(Some)
+                    };
+                    interface.set_prompt(&make_prompt(
+                        &localnode,
+                        &query,
+                        &settings.lock().unwrap().theme,
+                    ))?;
+                    match text {
+                        Ok(Some(text)) if !text.trim().is_empty() => {
+                            if let Err(e) = send_sms(
+                                tx.clone(),
+                                endpoint.clone(),
+                                dst,
+                                &settings.lock().unwrap(),
+                                &text,
+                            ) {
+                                println!("{}", e);
+                            }
+                        }
+                        Ok(Some(_)) => println!("Empty message, nothing sent"),
+                        Ok(None) => println!("Message discarded"),
+                        Err(e) => println!("{}", e),
+                    }
+                } else {
+                    println!("Please open query first or name a peer");
+                }
+            }
             "/quit" => break,
             _ => {
                 if line.starts_with('/') {
@@ -348,23 +1392,248 @@ fn main() -> Result<()> {
                 } else if query.is_none() {
                     println!("Please open query first");
                 } else if !line.is_empty() {
-                    send_sms(
+                    if let Err(e) = send_sms(
                         tx.clone(),
                         endpoint.clone(),
                         query.clone().unwrap(),
-                        lifetime,
+                        &settings.lock().unwrap(),
                         &line,
-                    )?;
+                    ) {
+                        println!("{}", e);
+                    }
                 }
             }
         }
     }
 
+    if settings.lock().unwrap().auto_save_history {
+        if let Err(e) = interface.save_history(HISTORY_FILE) {
+            eprintln!("Could not save history file {}: {}", HISTORY_FILE, e);
+        }
+    }
+    if settings.lock().unwrap().persist_stats {
+        if let Err(e) = stats.lock().unwrap().save() {
+            eprintln!("Could not save statistics {}: {}", stats::STATS_FILE, e);
+        }
+    }
     println!("Goodbye.");
 
     Ok(())
 }
 
+/// Endpoint and node of a peer or group, given by name or as a full endpoint
+fn peer_eid(dst_node: &str, groups: &HashSet<String>) -> Result<(EndpointID, String)> {
+    // full endpoints, e.g. to talk to other tools
+    let dst: EndpointID = if dst_node.contains(':') {
+        dst_node.try_into()?
+    } else {
+        roster::chat_eid(dst_node, groups.contains(dst_node))?
+    };
+    match dst.node() {
+        Some(node) => Ok((dst, node)),
+        None => bail!("{} is not the endpoint of a node", dst_node),
+    }
+}
+
+/// Current transcript entry of the chat message numbered `n` in `/recent`
+fn recent_entry(recent: &Recent, transcript: &Transcript, n: &str) -> Option<Entry> {
+    let n = n.trim_start_matches('#').parse::<u64>().ok()?;
+    let bid = recent.get(n)?.bid.as_ref()?;
+    transcript.get(bid).cloned()
+}
+
+/// Peer or group a conversation about `entry` takes place with
+fn conversation_peer<'a>(entry: &'a Entry, localnode: &str) -> &'a str {
+    if entry.outgoing || entry.dst != localnode {
+        &entry.dst
+    } else {
+        &entry.src
+    }
+}
+
+/// Show the setting `name`, changing it first unless `value` is empty
+fn set_setting(settings: &Mutex<Settings>, name: &str, value: &str) {
+    let mut settings = settings.lock().unwrap();
+    if !value.is_empty() {
+        if let Err(e) = settings.set(name, value) {
+            println!("{}", e);
+            return;
+        }
+    }
+    println!("{} = {}", name, settings.get(name).unwrap_or_default());
+}
+
+fn make_prompt(localnode: &EndpointID, query: &Option<EndpointID>, theme: &Theme) -> String {
+    match query {
+        None => format!(
+            "{}{} {}> {}",
+            pe(theme.prompt.to_string()),
+            localnode.node().unwrap(),
+            pe(theme.frame.to_string()),
+            pe(theme.reset())
+        ),
+        Some(query) => format!(
+            "{}{} {}>> {}{} {}> {}",
+            pe(theme.prompt.to_string()),
+            localnode.node().unwrap(),
+            pe(theme.frame.to_string()),
+            pe(theme.query.to_string()),
+            query.node().unwrap(),
+            pe(theme.frame.to_string()),
+            pe(theme.reset())
+        ),
+    }
+}
+
+/// Send our profile to `peers`, joined groups included
+///
+/// Peers that cannot be addressed or use plain text are skipped.
+fn send_profile<'a>(
+    tx: &Sender<WsCommand>,
+    src: &EndpointID,
+    peers: impl IntoIterator<Item = &'a String>,
+    groups: &HashSet<String>,
+    settings: &Settings,
+    me: &Profile,
+) -> Result<()> {
+    let payload = Control::Profile(me.clone()).to_cbor();
+    for peer in peers.into_iter().filter(|p| **p != me.node) {
+        let dst = match peer_eid(peer, groups) {
+            Ok((dst, _)) if settings.encoding_for(&dst) != Encoding::Plain => dst,
+            _ => continue,
+        };
+        let data = Outgoing {
+            src: src.clone(),
+            dst,
+            options: Default::default(),
+            lifetime: roster::BEACON_LIFETIME,
+            data: payload.clone(),
+        };
+        tx.send(WsCommand::SendData(data))?;
+    }
+    Ok(())
+}
+
+/// Parse `<text> [--regex] [--peer X] [--group Y] [--since 2d]`
+fn parse_search(args: &str) -> Result<Query> {
+    let mut query = Query::default();
+    let mut text = Vec::new();
+    let mut regex = false;
+    let mut words = args.split_whitespace();
+    while let Some(word) = words.next() {
+        match word {
+            "--regex" => regex = true,
+            "--peer" => query.peer = words.next().map(String::from),
+            "--group" => query.group = words.next().map(String::from),
+            "--since" => {
+                let since = parse_duration(words.next().unwrap_or_default())?;
+                query.since = Some(roster::unix_now().saturating_sub(since.as_secs()));
+            }
+            _ => text.push(word),
+        }
+    }
+    if text.is_empty() {
+        anyhow::bail!("Usage: /search <text> [--regex] [--peer X] [--group Y] [--since 2d]");
+    }
+    query.text = text.join(" ");
+    if regex {
+        query.regex = Some(regex::Regex::new(&format!("(?i){}", query.text))?);
+    }
+    Ok(query)
+}
+
+/// Print a transcript entry in the same colours as received messages
+fn print_entry(e: &Entry, time_format: TimeFormat, theme: &Theme) {
+    println!(
+        "{} {}({}){}",
+        theme.message(
+            &format_time(e.time, time_format),
+            &e.src,
+            &e.src,
+            Some(&e.dst),
+            &e.msg
+        ),
+        theme.dim,
+        e.bid,
+        theme.reset()
+    );
+}
+
+fn import_bundles(tx: &Sender<WsCommand>, dir: &Path) -> Result<()> {
+    let mut seen = spool::seen()?;
+    let mut imported = 0;
+    let mut skipped = 0;
+    for (path, bndl) in spool::load_dir(dir)? {
+        match bndl {
+            Ok(bndl) => {
+                let bid = bndl.id();
+                if seen.contains(&bid) {
+                    skipped += 1;
+                    continue;
+                }
+                spool::mark_seen(&bid)?;
+                seen.insert(bid);
+                tx.send(WsCommand::Inject(bndl))?;
+                imported += 1;
+            }
+            Err(e) => println!("Skipping {}: {}", path.display(), e),
+        }
+    }
+    println!(
+        "Imported {} bundles from {}, skipped {} duplicates",
+        imported,
+        dir.display(),
+        skipped
+    );
+    Ok(())
+}
+
+/// Read a message line by line, `None` if the user cancelled with Ctrl-D
+fn compose<Term: Terminal>(interface: &Interface<Term>) -> Result<Option<String>> {
+    println!("Enter message, finish with a single \".\" on a line, Ctrl-D to discard:");
+    interface.set_prompt("... ")?;
+    let mut lines = Vec::new();
+    loop {
+        match interface.read_line()? {
+            ReadResult::Input(line) if line == "." => return Ok(Some(lines.join("\n"))),
+            ReadResult::Input(line) => lines.push(line),
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// Let the user write a message in `$VISUAL` or `$EDITOR`
+fn edit_message() -> Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".into());
+    let path = std::env::temp_dir().join(format!(
+        "dtnchat-{}-{}.txt",
+        std::process::id(),
+        multipart::new_message_id()
+    ));
+    // never follow a link or reuse a file someone else prepared
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+    // like git, let the shell split arguments such as `code --wait`
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status();
+    let text = std::fs::read_to_string(&path);
+    std::fs::remove_file(&path).ok();
+    match status {
+        Ok(status) if status.success() => Ok(text?),
+        Ok(_) => anyhow::bail!("{} exited with an error, nothing sent", editor),
+        Err(e) => anyhow::bail!("could not start {}: {}, nothing sent", editor, e),
+    }
+}
+
 fn split_first_word(s: &str) -> (&str, &str) {
     let s = s.trim();
 
@@ -381,13 +1650,80 @@ static DTNCHAT_COMMANDS: &[(&str, &str)] = &[
         "Compose a new short message (groups must be joined first!)",
     ),
     ("/list", "List subscriptions"),
+    ("/who", "List members of a group"),
     ("/lifetime", "Manage message lifetime"),
+    ("/partsize", "Manage maximum size of a message part, same as /set part-size"),
+    ("/timefmt", "Show timestamps as local, utc, iso or relative, same as /set time-format"),
+    ("/theme", "Switch to a built-in colour theme, same as /set theme"),
+    ("/delay", "Show receive time and end-to-end delay (on|off), same as /set show-delay"),
+    ("/with", "Send a message with other bundle options, e.g. /with hop-limit=3 <peer> <message>"),
+    ("/bundle", "Show or change bundle options, globally or for one peer (reports, report-to, hop-limit, bundle-age, crc)"),
+    (
+        "/encoding",
+        "Show or set the payload encoding of a conversation (sms|plain)",
+    ),
+    (
+        "/listen",
+        "Receive bundles on another endpoint, e.g. of plain-text tools",
+    ),
+    ("/unlisten", "Stop receiving bundles on an endpoint"),
+    ("/at", "Send a message at a given time or after a delay"),
+    ("/schedules", "List scheduled messages"),
+    ("/unschedule", "Remove a scheduled message"),
+    ("/sneakernet", "Spool bundles to a directory (off to stop)"),
+    ("/import", "Import a directory of spooled .bundle files"),
     ("/peers", "List known peers"),
+    ("/nick", "Show or change nickname"),
+    ("/status", "Set or clear status line"),
+    (
+        "/ping",
+        "Probe a peer and measure round-trip times, list pings without arguments",
+    ),
+    (
+        "/trace",
+        "Show the path to a peer from status reports, list traces without arguments",
+    ),
+    (
+        "/stats",
+        "Show traffic, delivery ratio and delays, for one peer or reset them",
+    ),
+    ("/whois", "Show profile of a peer"),
+    (
+        "/highlight",
+        "List, add or remove (-word) highlight keywords",
+    ),
+    ("/mentions", "List recent messages mentioning us"),
+    ("/recent", "List recently received messages with their numbers"),
+    ("/info", "Show primary and canonical blocks of a recent bundle"),
+    ("/save", "Write the raw payload of a recent bundle to a file"),
+    ("/reply", "Answer a recent message quoting it"),
+    ("/react", "React to a recent message"),
+    ("/amend", "Correct one of your recent messages"),
+    ("/retract", "Retract one of your recent messages"),
+    (
+        "/search",
+        "Search the transcript of sent and received messages",
+    ),
+    ("/export", "Export a conversation as json, html or txt"),
+    ("/ignore", "Ignore a sender or group (* as wildcard)"),
+    ("/unignore", "Stop ignoring a sender or group"),
+    (
+        "/ratelimit",
+        "Mute senders above N messages per minute (0 = off)",
+    ),
     ("/join", "Join a group"),
     ("/leave", "Leave a group"),
     ("/help", "You're looking at it"),
+    (
+        "/compose",
+        "Write a multi-line message, end with a lone \".\"",
+    ),
+    ("/edit", "Write a message in $EDITOR"),
     ("/history", "Print history"),
     ("/save-history", "Write history to file"),
+    ("/set", "Change a setting, e.g. /set lifetime 2h"),
+    ("/get", "Show all settings or a single one"),
+    ("/save-settings", "Save settings to be used on the next start"),
     ("/quit", "Quit"),
 ];
 
@@ -421,23 +1757,51 @@ impl<Term: Terminal> Completer<Term> for DtnChatCompleter {
                 Some(compls)
             }
             // Complete command parameters
-            Some("/get") | Some("/set") => {
-                if words.count() == 0 {
+            Some("/get") | Some("/set") => match words.next() {
+                None => {
                     let mut res = Vec::new();
 
+                    for name in settings::NAMES {
+                        if name.starts_with(word) {
+                            res.push(Completion::simple(name.to_string()));
+                        }
+                    }
                     for (name, _) in prompter.variables() {
                         if name.starts_with(word) {
                             res.push(Completion::simple(name.to_owned()));
                         }
                     }
 
+                    Some(res)
+                }
+                // values only make sense for /set
+                Some(name) if line.starts_with("/set") && words.next().is_none() => Some(
+                    Settings::values(name)
+                        .iter()
+                        .filter(|v| v.starts_with(word))
+                        .map(|v| Completion::simple(v.to_string()))
+                        .collect(),
+                ),
+                _ => None,
+            },
+            // Complete command parameters
+            Some("/who") | Some("/whois") => {
+                if words.count() == 0 {
+                    let mut res = Vec::new();
+
+                    for name in self.eids.iter() {
+                        if name.starts_with(word) {
+                            res.push(Completion::simple(name.to_owned()));
+                        }
+                    }
+
                     Some(res)
                 } else {
                     None
                 }
             }
             // Complete command parameters
-            Some("/query") | Some("/msg") => {
+            Some("/query") | Some("/msg") | Some("/compose") | Some("/edit") => {
                 if words.count() == 0 {
                     let mut res = Vec::new();
 
@@ -0,0 +1,145 @@
+use std::collections::VecDeque;
+
+/// Number of mentions kept for `/mentions`
+pub const MAX_MENTIONS: usize = 50;
+
+#[derive(Debug, Clone)]
+pub struct Mention {
+    /// Unix time of the message creation
+    pub time: u64,
+    pub src: String,
+    pub dst: String,
+    pub msg: String,
+}
+
+/// Highlight keywords and the most recent messages that matched them
+#[derive(Debug, Default)]
+pub struct Mentions {
+    keywords: Vec<String>,
+    hits: VecDeque<Mention>,
+}
+
+/// Case-insensitive search for `word` not surrounded by other alphanumeric characters
+fn contains_word(text: &str, word: &str) -> bool {
+    if word.is_empty() {
+        return false;
+    }
+    let text = text.to_lowercase();
+    let word = word.to_lowercase();
+    let mut start = 0;
+    while let Some(pos) = text[start..].find(&word) {
+        let begin = start + pos;
+        let end = begin + word.len();
+        let before = text[..begin].chars().next_back();
+        let after = text[end..].chars().next();
+        if !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric) {
+            return true;
+        }
+        start = begin + text[begin..].chars().next().map_or(1, char::len_utf8);
+    }
+    false
+}
+
+impl Mentions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    pub fn keywords(&self) -> &[String] {
+        &self.keywords
+    }
+    pub fn add_keyword(&mut self, keyword: &str) -> bool {
+        if self
+            .keywords
+            .iter()
+            .any(|k| k.eq_ignore_ascii_case(keyword))
+        {
+            return false;
+        }
+        self.keywords.push(keyword.to_string());
+        true
+    }
+    pub fn remove_keyword(&mut self, keyword: &str) -> bool {
+        let len = self.keywords.len();
+        self.keywords.retain(|k| !k.eq_ignore_ascii_case(keyword));
+        len != self.keywords.len()
+    }
+    /// Check whether `msg` mentions one of our `names` or a keyword
+    pub fn matches(&self, msg: &str, names: &[&str]) -> bool {
+        names
+            .iter()
+            .copied()
+            .chain(self.keywords.iter().map(String::as_str))
+            .any(|w| contains_word(msg, w))
+    }
+    pub fn record(&mut self, mention: Mention) {
+        if self.hits.len() >= MAX_MENTIONS {
+            self.hits.pop_front();
+        }
+        self.hits.push_back(mention);
+    }
+    pub fn hits(&self) -> impl Iterator<Item = &Mention> {
+        self.hits.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_whole_words_only() {
+        assert!(contains_word("hi alice", "alice"));
+        assert!(contains_word("alice: are you there?", "alice"));
+        assert!(contains_word("@alice!", "alice"));
+        assert!(!contains_word("malice", "alice"));
+        assert!(!contains_word("alice2", "alice"));
+        assert!(!contains_word("", "alice"));
+        assert!(!contains_word("alice", ""));
+    }
+
+    #[test]
+    fn ignores_case() {
+        assert!(contains_word("Hello ALICE", "alice"));
+        assert!(contains_word("hello alice", "Alice"));
+    }
+
+    #[test]
+    fn keeps_searching_after_a_partial_match() {
+        assert!(contains_word("malice and alice", "alice"));
+        assert!(contains_word("bobbob bob", "bob"));
+    }
+
+    #[test]
+    fn handles_non_ascii_text() {
+        assert!(contains_word("grüße an jörg", "jörg"));
+        assert!(!contains_word("jörgen", "jörg"));
+        assert!(!contains_word("éalice", "alice"));
+    }
+
+    #[test]
+    fn matches_names_and_keywords() {
+        let mut mentions = Mentions::new();
+        assert!(mentions.add_keyword("release"));
+        assert!(!mentions.add_keyword("Release"));
+        assert!(mentions.matches("the release is out", &["node1"]));
+        assert!(mentions.matches("ping node1", &["node1", "Alice"]));
+        assert!(!mentions.matches("nothing here", &["node1"]));
+        assert!(mentions.remove_keyword("RELEASE"));
+        assert!(!mentions.matches("the release is out", &["node1"]));
+    }
+
+    #[test]
+    fn keeps_the_newest_mentions() {
+        let mut mentions = Mentions::new();
+        for time in 0..MAX_MENTIONS as u64 + 1 {
+            mentions.record(Mention {
+                time,
+                src: "alice".to_string(),
+                dst: "me".to_string(),
+                msg: "hi".to_string(),
+            });
+        }
+        assert_eq!(mentions.hits().count(), MAX_MENTIONS);
+        assert_eq!(mentions.hits().next().unwrap().time, 1);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::schedule::Schedule;
+use crate::stats::{Counters, Stats, DELAY_BUCKETS_MS};
+use crate::ws::WsCommand;
+use crossbeam_channel::Sender;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Scrapes taking longer than this to send or receive are dropped
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// State exported on `/metrics` in the Prometheus text format
+#[derive(Clone)]
+pub struct Metrics {
+    pub stats: Arc<Mutex<Stats>>,
+    /// Whether we are subscribed to dtnd
+    pub connected: Arc<Mutex<bool>>,
+    /// Commands waiting for the send listener
+    pub queue: Sender<WsCommand>,
+    pub schedule: Arc<Mutex<Schedule>>,
+}
+
+type CounterFn = fn(&Counters) -> u64;
+
+/// Counters exported in total and per peer
+const COUNTERS: &[(&str, &str, CounterFn)] = &[
+    (
+        "dtnchat_bundles_sent_total",
+        "Bundles handed to dtnd",
+        |c| c.bundles_sent,
+    ),
+    ("dtnchat_bytes_sent_total", "Bytes of sent bundles", |c| {
+        c.bytes_sent
+    }),
+    ("dtnchat_messages_sent_total", "Chat messages sent", |c| {
+        c.messages_sent
+    }),
+    (
+        "dtnchat_bundles_received_total",
+        "Bundles received from other nodes",
+        |c| c.bundles_received,
+    ),
+    (
+        "dtnchat_bytes_received_total",
+        "Bytes of received bundles",
+        |c| c.bytes_received,
+    ),
+    (
+        "dtnchat_messages_received_total",
+        "Chat messages received",
+        |c| c.messages_received,
+    ),
+    (
+        "dtnchat_delivery_reports_requested_total",
+        "Sent bundles asking for a delivery report",
+        |c| c.reports_requested,
+    ),
+    (
+        "dtnchat_delivered_total",
+        "Sent bundles reported as delivered",
+        |c| c.delivered,
+    ),
+];
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+/// Label value with backslash, double quote and newline escaped
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// One sample per peer, `sum()` gives the total
+fn per_peer(out: &mut String, stats: &Stats, name: &str, value: impl Fn(&Counters) -> u64) {
+    for (peer, c) in &stats.peers {
+        writeln!(
+            out,
+            "{}{{peer=\"{}\"}} {}",
+            name,
+            escape_label(peer),
+            value(c)
+        )
+        .unwrap();
+    }
+}
+
+impl Metrics {
+    pub fn render(&self) -> String {
+        let stats = self.stats.lock().unwrap();
+        let mut out = String::new();
+        for (name, help, value) in COUNTERS {
+            metric(&mut out, name, "counter", help);
+            per_peer(&mut out, &stats, name, value);
+        }
+
+        let name = "dtnchat_delivery_delay_seconds";
+        metric(
+            &mut out,
+            name,
+            "histogram",
+            "Time from creation of sent bundles to their delivery",
+        );
+        let histogram = &stats.total.delivery_histogram;
+        let mut cumulative = 0;
+        for (i, bound) in DELAY_BUCKETS_MS.iter().enumerate() {
+            cumulative += histogram.buckets.get(i).copied().unwrap_or(0);
+            writeln!(
+                out,
+                "{}_bucket{{le=\"{}\"}} {}",
+                name,
+                *bound as f64 / 1000.0,
+                cumulative
+            )
+            .unwrap();
+        }
+        writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, histogram.count).unwrap();
+        writeln!(out, "{}_sum {}", name, histogram.sum_ms as f64 / 1000.0).unwrap();
+        writeln!(out, "{}_count {}", name, histogram.count).unwrap();
+
+        let gauges = [
+            (
+                "dtnchat_connected",
+                "1 if subscribed to dtnd",
+                *self.connected.lock().unwrap() as u64,
+            ),
+            (
+                "dtnchat_last_received_timestamp_seconds",
+                "Unix time the last bundle from another node arrived, 0 if none yet",
+                stats.last_received.unwrap_or(0),
+            ),
+            (
+                "dtnchat_send_queue_length",
+                "Outgoing commands not yet handled",
+                self.queue.len() as u64,
+            ),
+            (
+                "dtnchat_pending_delivery_reports",
+                "Sent bundles still waiting for a delivery report",
+                stats.pending() as u64,
+            ),
+            (
+                "dtnchat_scheduled_messages",
+                "Messages scheduled with /at",
+                self.schedule.lock().unwrap().items().len() as u64,
+            ),
+        ];
+        for (name, help, value) in &gauges {
+            metric(&mut out, name, "gauge", help);
+            writeln!(out, "{} {}", name, value).unwrap();
+        }
+        out
+    }
+    fn respond(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut request = String::new();
+        BufReader::new(&stream).read_line(&mut request)?;
+        let path = request.split_whitespace().nth(1).unwrap_or_default();
+        let (status, body) = if path == "/metrics" {
+            ("200 OK", self.render())
+        } else {
+            ("404 Not Found", "only /metrics is served\n".to_string())
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+    /// Answer each scrape in its own thread, runs until the listener fails
+    pub fn serve(self, listener: TcpListener) {
+        for stream in listener.incoming().flatten() {
+            let metrics = self.clone();
+            thread::spawn(move || {
+                if let Err(e) = metrics.respond(stream) {
+                    eprintln!("Could not answer metrics request: {}", e);
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use crate::proto::Part;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+static MSG_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Messages with more parts are neither sent nor reassembled
+pub const MAX_PARTS: usize = 1024;
+/// Incomplete messages kept per sender, parts of further messages are dropped
+pub const MAX_PENDING_PER_SENDER: usize = 16;
+
+/// Unique id shared by all parts of one message
+pub fn new_message_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!(
+        "{:x}-{}",
+        nanos,
+        MSG_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Split `msg` into chunks of at most `max` bytes without breaking UTF-8 characters
+pub fn split(msg: &str, max: usize) -> Vec<String> {
+    let max = max.max(4);
+    let mut parts = Vec::new();
+    let mut rest = msg;
+    while !rest.is_empty() {
+        let mut end = rest.len().min(max);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        parts.push(rest[..end].to_string());
+        rest = &rest[end..];
+    }
+    parts
+}
+
+pub enum Reassembly {
+    /// All parts arrived, bundle id of the first part and the full text
+    Complete(String, String),
+    /// Number of parts received so far and total number of parts
+    Missing(usize, usize),
+    /// Part dropped because it exceeds a limit or contradicts earlier parts
+    Rejected,
+}
+
+struct Partial {
+    parts: Vec<Option<String>>,
+    first_bid: Option<String>,
+    expires: Instant,
+}
+
+/// Collects parts of multipart messages until they are complete
+#[derive(Default)]
+pub struct Reassembler {
+    pending: HashMap<(String, String), Partial>,
+}
+
+impl Reassembler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    /// Add a part received from `src` in bundle `bid`
+    ///
+    /// Incomplete messages are dropped once `lifetime` has passed.
+    pub fn add(&mut self, src: &str, bid: &str, part: Part, lifetime: Duration) -> Reassembly {
+        let now = Instant::now();
+        self.pending.retain(|_, p| p.expires > now);
+
+        let total = (part.total as usize).max(1);
+        let index = part.index as usize;
+        if total > MAX_PARTS || index >= total {
+            return Reassembly::Rejected;
+        }
+        let key = (src.to_string(), part.id.clone());
+        if !self.pending.contains_key(&key)
+            && self.pending.keys().filter(|(s, _)| s == src).count() >= MAX_PENDING_PER_SENDER
+        {
+            return Reassembly::Rejected;
+        }
+        let partial = self.pending.entry(key.clone()).or_insert_with(|| Partial {
+            parts: vec![None; total],
+            first_bid: None,
+            expires: now + lifetime,
+        });
+        if partial.parts.len() != total {
+            return Reassembly::Rejected;
+        }
+        partial.parts[index] = Some(part.text);
+        if index == 0 {
+            partial.first_bid = Some(bid.to_string());
+        }
+        let received = partial.parts.iter().filter(|p| p.is_some()).count();
+        if received < partial.parts.len() {
+            return Reassembly::Missing(received, partial.parts.len());
+        }
+        let partial = self.pending.remove(&key).unwrap();
+        let text: String = partial.parts.into_iter().flatten().collect();
+        Reassembly::Complete(partial.first_bid.unwrap_or_else(|| bid.to_string()), text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(id: &str, index: u32, total: u32, text: &str) -> Part {
+        Part {
+            id: id.to_string(),
+            index,
+            total,
+            text: text.to_string(),
+        }
+    }
+
+    const LIFETIME: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn split_keeps_chunks_below_max() {
+        assert_eq!(split("hello world", 4), vec!["hell", "o wo", "rld"]);
+        assert_eq!(split("short", 100), vec!["short"]);
+        assert!(split("", 10).is_empty());
+    }
+
+    #[test]
+    fn split_does_not_break_characters() {
+        // two bytes each, a 5 byte chunk would end inside the third character
+        assert_eq!(split("äöü", 5), vec!["äö", "ü"]);
+        assert_eq!(split("äöü", 5).concat(), "äöü");
+    }
+
+    #[test]
+    fn split_uses_at_least_four_bytes() {
+        assert_eq!(split("abcdefgh", 1), vec!["abcd", "efgh"]);
+    }
+
+    #[test]
+    fn reassembles_parts_in_any_order() {
+        let mut parts = Reassembler::new();
+        assert!(matches!(
+            parts.add("alice", "b2", part("m", 1, 3, "lo "), LIFETIME),
+            Reassembly::Missing(1, 3)
+        ));
+        assert!(matches!(
+            parts.add("alice", "b1", part("m", 0, 3, "hel"), LIFETIME),
+            Reassembly::Missing(2, 3)
+        ));
+        match parts.add("alice", "b3", part("m", 2, 3, "you"), LIFETIME) {
+            Reassembly::Complete(bid, text) => {
+                assert_eq!(bid, "b1");
+                assert_eq!(text, "hello you");
+            }
+            _ => panic!("message not complete"),
+        }
+    }
+
+    #[test]
+    fn keeps_senders_apart() {
+        let mut parts = Reassembler::new();
+        parts.add("alice", "b1", part("m", 0, 2, "a"), LIFETIME);
+        assert!(matches!(
+            parts.add("bob", "b2", part("m", 1, 2, "b"), LIFETIME),
+            Reassembly::Missing(1, 2)
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_parts() {
+        let mut parts = Reassembler::new();
+        let too_many = MAX_PARTS as u32 + 1;
+        assert!(matches!(
+            parts.add("alice", "b1", part("m", 0, too_many, "a"), LIFETIME),
+            Reassembly::Rejected
+        ));
+        assert!(matches!(
+            parts.add("alice", "b1", part("m", 2, 2, "a"), LIFETIME),
+            Reassembly::Rejected
+        ));
+        parts.add("alice", "b1", part("m", 0, 2, "a"), LIFETIME);
+        assert!(matches!(
+            parts.add("alice", "b2", part("m", 1, 3, "b"), LIFETIME),
+            Reassembly::Rejected
+        ));
+    }
+
+    #[test]
+    fn limits_pending_messages_per_sender() {
+        let mut parts = Reassembler::new();
+        for i in 0..MAX_PENDING_PER_SENDER {
+            let id = format!("m{}", i);
+            assert!(matches!(
+                parts.add("alice", "b", part(&id, 0, 2, "a"), LIFETIME),
+                Reassembly::Missing(1, 2)
+            ));
+        }
+        assert!(matches!(
+            parts.add("alice", "b", part("new", 0, 2, "a"), LIFETIME),
+            Reassembly::Rejected
+        ));
+        // parts of messages already pending and other senders still count
+        assert!(matches!(
+            parts.add("alice", "b", part("m0", 1, 2, "b"), LIFETIME),
+            Reassembly::Complete(..)
+        ));
+        assert!(matches!(
+            parts.add("bob", "b", part("new", 0, 2, "a"), LIFETIME),
+            Reassembly::Missing(1, 2)
+        ));
+    }
+}
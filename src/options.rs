@@ -0,0 +1,240 @@
+use anyhow::{bail, Result};
+use bp7::canonical::{new_bundle_age_block, new_hop_count_block};
+use bp7::crc::{CRC_16, CRC_32};
+use bp7::flags::{BlockControlFlags, BundleControlFlags};
+use bp7::{Bundle, EndpointID};
+use std::convert::TryInto;
+use std::fmt;
+
+/// Names accepted by [`BundleOptions::set`]
+pub const OPTIONS: &[&str] = &["reports", "report-to", "hop-limit", "bundle-age", "crc"];
+
+/// Status reports requested from nodes handling a bundle
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Reports {
+    pub reception: bool,
+    pub forwarding: bool,
+    pub delivery: bool,
+    pub deletion: bool,
+}
+
+impl Reports {
+    pub fn delivery() -> Reports {
+        Reports {
+            delivery: true,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Crc {
+    #[default]
+    None,
+    Crc16,
+    Crc32,
+}
+
+/// Primary block flags and extension blocks of an outgoing bundle
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BundleOptions {
+    pub reports: Reports,
+    /// Where status reports are sent, `dtn:none` if unset
+    pub report_to: Option<EndpointID>,
+    /// Add a Hop Count block with this limit
+    pub hop_limit: Option<u8>,
+    /// Add a Bundle Age block so nodes without clock can tell the age
+    pub bundle_age: bool,
+    pub crc: Crc,
+}
+
+/// Parse a boolean option as entered by the user
+pub fn on_off(value: &str) -> Result<bool> {
+    match value {
+        "on" | "true" | "yes" => Ok(true),
+        "off" | "false" | "no" => Ok(false),
+        _ => bail!("expected on or off, got {}", value),
+    }
+}
+
+impl BundleOptions {
+    /// Change the option `name` to `value`, both as entered by the user
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        match name {
+            "reports" => {
+                let mut reports = Reports::default();
+                for r in value.split(',').filter(|r| *r != "none") {
+                    match r {
+                        "reception" => reports.reception = true,
+                        "forwarding" => reports.forwarding = true,
+                        "delivery" => reports.delivery = true,
+                        "deletion" => reports.deletion = true,
+                        _ => bail!(
+                            "unknown report {}, use reception, forwarding, delivery, deletion or none",
+                            r
+                        ),
+                    }
+                }
+                self.reports = reports;
+            }
+            "report-to" => {
+                self.report_to = match value {
+                    "none" => None,
+                    eid => Some(eid.try_into()?),
+                }
+            }
+            "hop-limit" => {
+                self.hop_limit = match value {
+                    "off" => None,
+                    limit => Some(limit.parse()?),
+                }
+            }
+            "bundle-age" => self.bundle_age = on_off(value)?,
+            "crc" => {
+                self.crc = match value {
+                    "none" => Crc::None,
+                    "16" => Crc::Crc16,
+                    "32" => Crc::Crc32,
+                    _ => bail!("unknown crc type {}, use none, 16 or 32", value),
+                }
+            }
+            _ => bail!("unknown option {}, use {}", name, OPTIONS.join(", ")),
+        }
+        Ok(())
+    }
+    /// Change several options given as `name=value` words, as shown by `Display`
+    pub fn set_all(&mut self, options: &str) -> Result<()> {
+        for option in options.split_whitespace() {
+            match option.split_once('=') {
+                Some((name, value)) => self.set(name, value)?,
+                None => bail!("expected option=value, got {}", option),
+            }
+        }
+        Ok(())
+    }
+    pub fn flags(&self) -> BundleControlFlags {
+        let mut flags = BundleControlFlags::empty();
+        flags.set(
+            BundleControlFlags::BUNDLE_STATUS_REQUEST_RECEPTION,
+            self.reports.reception,
+        );
+        flags.set(
+            BundleControlFlags::BUNDLE_STATUS_REQUEST_FORWARD,
+            self.reports.forwarding,
+        );
+        flags.set(
+            BundleControlFlags::BUNDLE_STATUS_REQUEST_DELIVERY,
+            self.reports.delivery,
+        );
+        flags.set(
+            BundleControlFlags::BUNDLE_STATUS_REQUEST_DELETION,
+            self.reports.deletion,
+        );
+        flags
+    }
+    /// Add report-to, extension blocks and CRCs to a freshly built bundle
+    pub fn apply(&self, bndl: &mut Bundle) {
+        if let Some(report_to) = &self.report_to {
+            bndl.primary.report_to = report_to.clone();
+        }
+        if let Some(limit) = self.hop_limit {
+            bndl.add_canonical_block(new_hop_count_block(0, BlockControlFlags::empty(), limit));
+        }
+        if self.bundle_age {
+            bndl.add_canonical_block(new_bundle_age_block(0, BlockControlFlags::empty(), 0));
+        }
+        match self.crc {
+            Crc::None => {}
+            Crc::Crc16 => bndl.set_crc(CRC_16),
+            Crc::Crc32 => bndl.set_crc(CRC_32),
+        }
+    }
+}
+
+impl fmt::Display for BundleOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reports: Vec<&str> = [
+            ("reception", self.reports.reception),
+            ("forwarding", self.reports.forwarding),
+            ("delivery", self.reports.delivery),
+            ("deletion", self.reports.deletion),
+        ]
+        .iter()
+        .filter(|(_, on)| *on)
+        .map(|(name, _)| *name)
+        .collect();
+        write!(
+            f,
+            "reports={} report-to={} hop-limit={} bundle-age={} crc={}",
+            if reports.is_empty() {
+                "none".to_string()
+            } else {
+                reports.join(",")
+            },
+            self.report_to
+                .as_ref()
+                .map_or("none".to_string(), |eid| eid.to_string()),
+            self.hop_limit
+                .map_or("off".to_string(), |limit| limit.to_string()),
+            if self.bundle_age { "on" } else { "off" },
+            match self.crc {
+                Crc::None => "none",
+                Crc::Crc16 => "16",
+                Crc::Crc32 => "32",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_round_trips_through_set_all() {
+        let options = BundleOptions {
+            reports: Reports {
+                reception: true,
+                deletion: true,
+                ..Default::default()
+            },
+            report_to: Some("dtn://reports/".try_into().unwrap()),
+            hop_limit: Some(3),
+            bundle_age: true,
+            crc: Crc::Crc32,
+        };
+        let mut parsed = BundleOptions::default();
+        parsed.set_all(&options.to_string()).unwrap();
+        assert_eq!(parsed, options);
+
+        let mut parsed = options.clone();
+        parsed
+            .set_all(&BundleOptions::default().to_string())
+            .unwrap();
+        assert_eq!(parsed, BundleOptions::default());
+    }
+
+    #[test]
+    fn set_rejects_unknown_values() {
+        let mut options = BundleOptions::default();
+        assert!(options.set("reports", "everything").is_err());
+        assert!(options.set("crc", "64").is_err());
+        assert!(options.set("hop-limit", "300").is_err());
+        assert!(options.set("colour", "red").is_err());
+        assert!(options.set_all("crc").is_err());
+        assert_eq!(options, BundleOptions::default());
+    }
+
+    #[test]
+    fn flags_follow_reports() {
+        let options = BundleOptions {
+            reports: Reports::delivery(),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.flags(),
+            BundleControlFlags::BUNDLE_STATUS_REQUEST_DELIVERY
+        );
+        assert!(BundleOptions::default().flags().is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+use serde_cbor::Value;
+
+/// Bytes shown in the hexdump of binary payloads
+pub const PREVIEW_BYTES: usize = 64;
+/// Characters shown of the CBOR diagnostic notation
+pub const PREVIEW_CHARS: usize = 200;
+
+/// How a payload that is not an SMS is shown
+pub enum Preview {
+    Text(String),
+    /// Payload decodes as CBOR, in diagnostic notation
+    Cbor(String),
+    /// Hexdump lines of the beginning of the payload
+    Binary(Vec<String>),
+}
+
+/// Printable UTF-8 text, `None` for binary data
+fn as_text(data: &[u8]) -> Option<&str> {
+    let text = std::str::from_utf8(data).ok()?;
+    if text.is_empty() || text.chars().any(|c| c.is_control() && !c.is_whitespace()) {
+        return None;
+    }
+    Some(text)
+}
+
+/// Hexdump with offset, 16 bytes per line and an ASCII column
+pub fn hexdump(data: &[u8]) -> Vec<String> {
+    data.chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|b| {
+                    if b.is_ascii_graphic() || *b == b' ' {
+                        *b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:04x}  {:47}  |{}|", i * 16, hex.join(" "), ascii)
+        })
+        .collect()
+}
+
+/// CBOR diagnostic notation as in RFC 8949 section 8
+pub fn cbor_diag(value: &Value) -> String {
+    match value {
+        Value::Null => "null".into(),
+        Value::Bool(b) => b.to_string(),
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Bytes(b) => format!(
+            "h'{}'",
+            b.iter().map(|b| format!("{:02x}", b)).collect::<String>()
+        ),
+        Value::Text(t) => format!("{:?}", t),
+        Value::Array(a) => format!(
+            "[{}]",
+            a.iter().map(cbor_diag).collect::<Vec<_>>().join(", ")
+        ),
+        Value::Map(m) => format!(
+            "{{{}}}",
+            m.iter()
+                .map(|(k, v)| format!("{}: {}", cbor_diag(k), cbor_diag(v)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Value::Tag(tag, v) => format!("{}({})", tag, cbor_diag(v)),
+        _ => "undefined".into(),
+    }
+}
+
+pub fn preview(data: &[u8]) -> Preview {
+    if let Some(text) = as_text(data) {
+        return Preview::Text(text.to_string());
+    }
+    if let Ok(value) = serde_cbor::from_slice::<Value>(data) {
+        return Preview::Cbor(cbor_diag(&value));
+    }
+    Preview::Binary(hexdump(&data[..data.len().min(PREVIEW_BYTES)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn previews_text() {
+        assert!(matches!(preview(b"hello\nworld"), Preview::Text(t) if t == "hello\nworld"));
+    }
+
+    #[test]
+    fn previews_cbor() {
+        // [1, 2, 3]
+        assert!(matches!(preview(&[0x83, 1, 2, 3]), Preview::Cbor(d) if d == "[1, 2, 3]"));
+    }
+
+    #[test]
+    fn previews_binary_as_hexdump() {
+        match preview(&[0xff, 0x00, 0x41]) {
+            Preview::Binary(lines) => {
+                assert_eq!(lines.len(), 1);
+                assert!(lines[0].starts_with("0000  ff 00 41"));
+                assert!(lines[0].ends_with("|..A|"));
+            }
+            _ => panic!("not shown as binary"),
+        }
+        match preview(&[0xff; 100]) {
+            Preview::Binary(lines) => assert_eq!(lines.len(), PREVIEW_BYTES / 16),
+            _ => panic!("not shown as binary"),
+        }
+    }
+
+    #[test]
+    fn cbor_diagnostic_notation() {
+        let mut map = BTreeMap::new();
+        map.insert(Value::Integer(1), Value::Text("a\"b".into()));
+        map.insert(Value::Text("k".into()), Value::Bytes(vec![0xde, 0xad]));
+        let value = Value::Array(vec![
+            Value::Null,
+            Value::Bool(true),
+            Value::Integer(-7),
+            Value::Map(map),
+            Value::Tag(1, Box::new(Value::Integer(0))),
+        ]);
+        assert_eq!(
+            cbor_diag(&value),
+            r#"[null, true, -7, {1: "a\"b", "k": h'dead'}, 1(0)]"#
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use crate::multipart::new_message_id;
+use crate::proto::{Control, Echo, Ping};
+use crate::ws::{Outgoing, WsCommand};
+use bp7::EndpointID;
+use crossbeam_channel::Sender;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Time between two probes of the same `/ping`
+pub const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+pub fn unix_now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Result of a single answered probe
+#[derive(Debug, Clone)]
+pub struct Probe {
+    pub seq: u32,
+    /// Round-trip time in milliseconds
+    pub rtt: u64,
+    /// Delay to the peer in milliseconds, only meaningful with synchronized clocks
+    pub one_way: i64,
+}
+
+/// Probes sent by one `/ping`
+#[derive(Debug)]
+pub struct Session {
+    pub id: String,
+    pub peer: String,
+    pub count: u32,
+    /// Unix time in milliseconds each probe was sent
+    sent: HashMap<u32, u64>,
+    pub answered: Vec<Probe>,
+}
+
+impl Session {
+    pub fn sent(&self) -> usize {
+        self.sent.len()
+    }
+    /// Percentage of sent probes not answered yet
+    pub fn loss(&self) -> f64 {
+        if self.sent.is_empty() {
+            return 0.0;
+        }
+        100.0 * (self.sent.len() - self.answered.len()) as f64 / self.sent.len() as f64
+    }
+    /// Minimum, average and maximum round-trip time in milliseconds
+    pub fn rtt_stats(&self) -> Option<(u64, u64, u64)> {
+        let rtts = self.answered.iter().map(|p| p.rtt);
+        let min = rtts.clone().min()?;
+        let max = rtts.clone().max()?;
+        let avg = rtts.sum::<u64>() / self.answered.len() as u64;
+        Some((min, avg, max))
+    }
+}
+
+/// All pings of this session, answers may arrive hours later
+#[derive(Debug, Default)]
+pub struct Pings {
+    sessions: Vec<Session>,
+}
+
+impl Pings {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    pub fn start(&mut self, peer: &str, count: u32) -> String {
+        let id = new_message_id();
+        self.sessions.push(Session {
+            id: id.clone(),
+            peer: peer.to_string(),
+            count,
+            sent: HashMap::new(),
+            answered: Vec::new(),
+        });
+        id
+    }
+    pub fn sent(&mut self, id: &str, seq: u32, at: u64) {
+        if let Some(s) = self.sessions.iter_mut().find(|s| s.id == id) {
+            s.sent.insert(seq, at);
+        }
+    }
+    /// Match an echo to its probe, `None` for unknown or duplicate echoes
+    pub fn echo(&mut self, echo: &Echo, now: u64) -> Option<(&Session, Probe)> {
+        let session = self.sessions.iter_mut().find(|s| s.id == echo.id)?;
+        let sent = *session.sent.get(&echo.seq)?;
+        if session.answered.iter().any(|p| p.seq == echo.seq) {
+            return None;
+        }
+        let probe = Probe {
+            seq: echo.seq,
+            rtt: now.saturating_sub(sent),
+            one_way: echo.received as i64 - sent as i64,
+        };
+        session.answered.push(probe.clone());
+        Some((session, probe))
+    }
+    pub fn sessions(&self) -> &[Session] {
+        &self.sessions
+    }
+}
+
+/// Send `count` probes to `dst`, one every [`PING_INTERVAL`]
+pub fn ping_loop(
+    pings: Arc<Mutex<Pings>>,
+    id: String,
+    count: u32,
+    tx: Sender<WsCommand>,
+    src: EndpointID,
+    dst: EndpointID,
+    lifetime: Duration,
+) {
+    for seq in 1..=count {
+        if seq > 1 {
+            thread::sleep(PING_INTERVAL);
+        }
+        let sent = unix_now_ms();
+        pings.lock().unwrap().sent(&id, seq, sent);
+        let ping = Control::Ping(Ping {
+            id: id.clone(),
+            seq,
+            sent,
+            ..Default::default()
+        });
+        let data = Outgoing {
+            src: src.clone(),
+            dst: dst.clone(),
+            options: Default::default(),
+            lifetime,
+            data: ping.to_cbor(),
+        };
+        if tx.send(WsCommand::SendData(data)).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use crate::proto::Profile;
+use std::collections::HashMap;
+
+/// Nicknames and status lines of known peers
+///
+/// Profiles are keyed by node ID, so two peers picking the same nickname are still told
+/// apart by their node in `nick (node)`.
+#[derive(Debug, Default)]
+pub struct Profiles {
+    by_node: HashMap<String, Profile>,
+}
+
+impl Profiles {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    /// Store a profile received from `src_node`
+    ///
+    /// Returns false if the profile claims to be from a different node than the bundle
+    /// it arrived in.
+    pub fn update(&mut self, src_node: &str, profile: Profile) -> bool {
+        if profile.node != src_node {
+            return false;
+        }
+        self.by_node.insert(profile.node.clone(), profile);
+        true
+    }
+    pub fn get(&self, node: &str) -> Option<&Profile> {
+        self.by_node.get(node)
+    }
+    /// Name used when showing messages of `node`
+    pub fn display(&self, node: &str) -> String {
+        match self.by_node.get(node) {
+            Some(p) if !p.nick.is_empty() && p.nick != node => format!("{} ({})", p.nick, node),
+            _ => node.to_string(),
+        }
+    }
+}
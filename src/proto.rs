@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+
+/// Control messages exchanged between dtnchat instances
+///
+/// They are sent as plain CBOR payloads next to regular SMS bundles. Other SMS clients
+/// fail to decode them as SMS and simply ignore them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "dtnchat")]
+pub enum Control {
+    /// Periodic announcement of a group member
+    Presence(Presence),
+    /// Nickname and status line of the sending node
+    Profile(Profile),
+    /// One piece of a message too long for a single bundle
+    Part(Part),
+    /// Answer to an earlier message
+    Reply(Reply),
+    /// Reaction to, correction or retraction of an earlier message
+    Annotate(Annotation),
+    /// Reachability probe, answered automatically with an echo
+    Ping(Ping),
+    Echo(Echo),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Presence {
+    pub nick: String,
+    pub node: String,
+    /// Unix time in seconds when the beacon was sent
+    pub last_seen: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub node: String,
+    pub nick: String,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Part {
+    /// Shared by all parts of the same message
+    pub id: String,
+    /// Position of this part, starting at 0
+    pub index: u32,
+    pub total: u32,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reply {
+    /// Bundle id of the message answered
+    pub reply_to: String,
+    /// Node that sent the message answered
+    pub quote_src: String,
+    /// Beginning of the message answered
+    pub quote: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Action {
+    React(String),
+    /// Replace the text of the message
+    Amend(String),
+    /// Ask recipients to hide the message
+    Retract,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    /// Bundle id of the message this applies to
+    pub target: String,
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Ping {
+    /// Identifies the `/ping` this probe belongs to
+    pub id: String,
+    pub seq: u32,
+    /// Unix time in milliseconds when the probe was sent
+    pub sent: u64,
+    /// Filler to bring probes of `dtnchat-bench` to a given size, not echoed
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub padding: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Echo {
+    pub id: String,
+    pub seq: u32,
+    /// Unix time in milliseconds when the probe was received by the peer
+    pub received: u64,
+}
+
+impl Control {
+    pub fn to_cbor(&self) -> Vec<u8> {
+        serde_cbor::to_vec(self).expect("Fatal failure, could not convert control message to CBOR")
+    }
+    /// Decode a bundle payload, returns `None` for anything that is not a control message
+    pub fn from_cbor(payload: &[u8]) -> Option<Control> {
+        serde_cbor::from_slice(payload).ok()
+    }
+}
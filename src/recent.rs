@@ -0,0 +1,53 @@
+use bp7::Bundle;
+use std::collections::VecDeque;
+
+/// Number of received bundles kept for `/recent`, `/reply` and friends
+pub const MAX_RECENT: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct RecentItem {
+    /// Running number used to refer to this item in commands
+    pub n: u64,
+    /// Unix time when the bundle was received
+    pub received: u64,
+    pub bundle: Bundle,
+    /// Bundle id of the transcript entry for chat messages, look it up there
+    /// to see later corrections and retractions
+    pub bid: Option<String>,
+}
+
+/// The most recently received bundles
+#[derive(Debug, Default)]
+pub struct Recent {
+    items: VecDeque<RecentItem>,
+    next: u64,
+}
+
+impl Recent {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    /// Remember a bundle and return its number
+    pub fn push(&mut self, received: u64, bundle: Bundle, bid: Option<String>) -> u64 {
+        self.next += 1;
+        if self.items.len() >= MAX_RECENT {
+            self.items.pop_front();
+        }
+        self.items.push_back(RecentItem {
+            n: self.next,
+            received,
+            bundle,
+            bid,
+        });
+        self.next
+    }
+    pub fn get(&self, n: u64) -> Option<&RecentItem> {
+        self.items.iter().find(|i| i.n == n)
+    }
+    /// The last `count` items, oldest first
+    pub fn last(&self, count: usize) -> impl Iterator<Item = &RecentItem> {
+        self.items
+            .iter()
+            .skip(self.items.len().saturating_sub(count))
+    }
+}
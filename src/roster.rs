@@ -0,0 +1,206 @@
+use crate::proto::{Control, Presence, Profile};
+use crate::ws::{Outgoing, WsCommand};
+use anyhow::Result;
+use bp7::EndpointID;
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Default time between two presence beacons
+pub const BEACON_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Shorter intervals would flood the joined groups
+pub const MIN_BEACON_INTERVAL: Duration = Duration::from_secs(10);
+/// Beacons should survive long disruptions
+pub const BEACON_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Chat endpoint of a peer, or of a group as created by `/join`
+pub fn chat_eid(name: &str, group: bool) -> Result<EndpointID> {
+    let eid = if let Ok(num) = name.parse::<u64>() {
+        format!("ipn:{}.767", num)
+    } else if group {
+        format!("dtn://{}/~sms", name)
+    } else {
+        format!("dtn://{}/sms", name)
+    };
+    Ok(eid.as_str().try_into()?)
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub nick: String,
+    pub node: String,
+    /// Unix time of the newest beacon or message from this member
+    pub last_seen: u64,
+}
+
+/// Members of all groups as learned from presence beacons
+#[derive(Debug, Default)]
+pub struct Roster {
+    groups: HashMap<String, HashMap<String, Member>>,
+}
+
+impl Roster {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    /// Apply a beacon `node` sent to `group`, received at unix time `received`
+    ///
+    /// The node is taken from the bundle, not the payload, so nobody can announce
+    /// others, and beacons from the future cannot keep a member online forever.
+    pub fn update(&mut self, group: &str, node: &str, presence: &Presence, received: u64) {
+        let member = self
+            .groups
+            .entry(group.to_string())
+            .or_default()
+            .entry(node.to_string())
+            .or_insert_with(|| Member {
+                nick: presence.nick.clone(),
+                node: node.to_string(),
+                last_seen: 0,
+            });
+        member.nick = presence.nick.clone();
+        member.last_seen = member.last_seen.max(presence.last_seen.min(received));
+    }
+    /// Record activity of a node in a group, e.g. a received message
+    pub fn touch(&mut self, group: &str, node: &str, time: u64) {
+        let member = self
+            .groups
+            .entry(group.to_string())
+            .or_default()
+            .entry(node.to_string())
+            .or_insert_with(|| Member {
+                nick: node.to_string(),
+                node: node.to_string(),
+                last_seen: 0,
+            });
+        member.last_seen = member.last_seen.max(time);
+    }
+    /// Members of a group, most recently seen first
+    pub fn members(&self, group: &str) -> Vec<Member> {
+        let mut members: Vec<Member> = self
+            .groups
+            .get(group)
+            .map(|m| m.values().cloned().collect())
+            .unwrap_or_default();
+        members.sort_by_key(|m| std::cmp::Reverse(m.last_seen));
+        members
+    }
+}
+
+/// Periodically announce ourselves in all joined groups
+///
+/// The current set of groups is pushed through `groups_rx` on every `/join` or `/leave`,
+/// newly joined groups get a beacon right away.
+pub fn beacon_loop(
+    groups_rx: Receiver<HashSet<String>>,
+    tx: Sender<WsCommand>,
+    src: EndpointID,
+    me: Arc<Mutex<Profile>>,
+    interval: Duration,
+) {
+    let mut groups: HashSet<String> = HashSet::new();
+    loop {
+        let targets: Vec<String> = match groups_rx.recv_timeout(interval) {
+            Ok(new_groups) => {
+                let added = new_groups.difference(&groups).cloned().collect();
+                groups = new_groups;
+                added
+            }
+            Err(RecvTimeoutError::Timeout) => groups.iter().cloned().collect(),
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        for group in targets {
+            if let Ok(dst) = chat_eid(&group, true) {
+                let beacon = {
+                    let me = me.lock().unwrap();
+                    Control::Presence(Presence {
+                        nick: me.nick.clone(),
+                        node: me.node.clone(),
+                        last_seen: unix_now(),
+                    })
+                };
+                let data = Outgoing {
+                    src: src.clone(),
+                    dst,
+                    options: Default::default(),
+                    lifetime: BEACON_LIFETIME,
+                    data: beacon.to_cbor(),
+                };
+                if tx.send(WsCommand::SendData(data)).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn presence(nick: &str, node: &str, last_seen: u64) -> Presence {
+        Presence {
+            nick: nick.to_string(),
+            node: node.to_string(),
+            last_seen,
+        }
+    }
+
+    #[test]
+    fn beacons_add_members_by_bundle_source() {
+        let mut roster = Roster::new();
+        // the payload claims to be bob, the bundle came from alice
+        roster.update("group", "alice", &presence("Alice", "bob", 100), 100);
+        let members = roster.members("group");
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].node, "alice");
+        assert_eq!(members[0].nick, "Alice");
+        assert!(roster.members("other").is_empty());
+    }
+
+    #[test]
+    fn last_seen_is_clamped_to_the_receive_time() {
+        let mut roster = Roster::new();
+        roster.update("group", "alice", &presence("a", "alice", u64::MAX), 100);
+        assert_eq!(roster.members("group")[0].last_seen, 100);
+        // older beacons arriving late do not move it back
+        roster.update("group", "alice", &presence("a", "alice", 50), 200);
+        assert_eq!(roster.members("group")[0].last_seen, 100);
+    }
+
+    #[test]
+    fn members_are_sorted_by_last_seen() {
+        let mut roster = Roster::new();
+        roster.update("group", "alice", &presence("a", "alice", 100), 100);
+        roster.update("group", "bob", &presence("b", "bob", 300), 300);
+        roster.touch("group", "carol", 200);
+        let nodes: Vec<String> = roster
+            .members("group")
+            .into_iter()
+            .map(|m| m.node)
+            .collect();
+        assert_eq!(nodes, vec!["bob", "carol", "alice"]);
+    }
+
+    #[test]
+    fn chat_endpoints() {
+        assert_eq!(chat_eid("42", false).unwrap().to_string(), "ipn:42.767");
+        assert_eq!(
+            chat_eid("node1", false).unwrap().to_string(),
+            "dtn://node1/sms"
+        );
+        assert_eq!(
+            chat_eid("group1", true).unwrap().to_string(),
+            "dtn://group1/~sms"
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::roster::unix_now;
+use crate::settings::Settings;
+use crate::ws::{send_sms, WsCommand};
+use anyhow::{bail, Result};
+use bp7::EndpointID;
+use chrono::{Duration as ChronoDuration, Local, NaiveDateTime, NaiveTime, TimeZone};
+use crossbeam_channel::{SendError, Sender};
+use humantime::parse_duration;
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Pending scheduled messages as JSON
+pub const SCHEDULE_FILE: &str = "dtnchat.schedule";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scheduled {
+    pub id: u64,
+    /// Unix time at which the message is sent
+    pub at: u64,
+    pub dst: String,
+    pub msg: String,
+}
+
+/// Whether `s` has the form HH:MM or HH:MM:SS, the second word of an absolute time
+pub fn is_clock_time(s: &str) -> bool {
+    let fields: Vec<&str> = s.split(':').collect();
+    (2..=3).contains(&fields.len())
+        && fields
+            .iter()
+            .all(|f| (1..=2).contains(&f.len()) && f.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Parse `+duration`, `YYYY-MM-DD HH:MM[:SS]` or `HH:MM[:SS]` into unix time
+///
+/// A time of day in the past refers to the next day.
+pub fn parse_time(s: &str) -> Result<u64> {
+    if let Some(d) = s.strip_prefix('+') {
+        return Ok(unix_now() + parse_duration(d)?.as_secs());
+    }
+    for fmt in &["%F %T", "%F %R", "%FT%T", "%FT%R"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(s, fmt) {
+            if let Some(dt) = Local.from_local_datetime(&dt).earliest() {
+                return Ok(dt.timestamp() as u64);
+            }
+        }
+    }
+    for fmt in &["%T", "%R"] {
+        if let Ok(t) = NaiveTime::parse_from_str(s, fmt) {
+            let now = Local::now();
+            let mut date = now.date();
+            if now.time() >= t {
+                date = date + ChronoDuration::days(1);
+            }
+            if let Some(dt) = date.and_time(t) {
+                return Ok(dt.timestamp() as u64);
+            }
+        }
+    }
+    bail!(
+        "invalid time {}, use +duration, HH:MM or YYYY-MM-DD HH:MM",
+        s
+    )
+}
+
+#[derive(Debug, Default)]
+pub struct Schedule {
+    items: Vec<Scheduled>,
+    next_id: u64,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    pub fn load() -> Result<Self> {
+        let items: Vec<Scheduled> = match fs::read_to_string(SCHEDULE_FILE) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let next_id = items.iter().map(|i| i.id + 1).max().unwrap_or(0);
+        Ok(Schedule { items, next_id })
+    }
+    pub fn save(&self) -> Result<()> {
+        fs::write(SCHEDULE_FILE, serde_json::to_string_pretty(&self.items)?)?;
+        Ok(())
+    }
+    pub fn add(&mut self, at: u64, dst: &EndpointID, msg: &str) -> Result<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.items.push(Scheduled {
+            id,
+            at,
+            dst: dst.to_string(),
+            msg: msg.to_string(),
+        });
+        self.items.sort_by_key(|i| i.at);
+        if let Err(e) = self.save() {
+            // not scheduled unless it survives a restart
+            self.items.retain(|i| i.id != id);
+            return Err(e);
+        }
+        Ok(id)
+    }
+    pub fn remove(&mut self, id: u64) -> Result<bool> {
+        let len = self.items.len();
+        self.items.retain(|i| i.id != id);
+        if len != self.items.len() {
+            self.save()?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+    pub fn items(&self) -> &[Scheduled] {
+        &self.items
+    }
+    /// Take all messages that are due
+    fn take_due(&mut self, now: u64) -> Vec<Scheduled> {
+        let (due, pending) = self.items.drain(..).partition(|i| i.at <= now);
+        self.items = pending;
+        due
+    }
+}
+
+/// Send scheduled messages once they are due, using the settings current at that moment
+pub fn schedule_loop(
+    schedule: Arc<Mutex<Schedule>>,
+    tx: Sender<WsCommand>,
+    src: EndpointID,
+    settings: Arc<Mutex<Settings>>,
+) {
+    loop {
+        thread::sleep(Duration::from_secs(1));
+        let due = {
+            let mut schedule = schedule.lock().unwrap();
+            let due = schedule.take_due(unix_now());
+            if !due.is_empty() {
+                if let Err(e) = schedule.save() {
+                    eprintln!("Could not save schedule {}: {}", SCHEDULE_FILE, e);
+                }
+            }
+            due
+        };
+        for item in due {
+            let dst: EndpointID = match item.dst.as_str().try_into() {
+                Ok(dst) => dst,
+                Err(_) => continue,
+            };
+            let settings = settings.lock().unwrap().clone();
+            if let Err(e) = send_sms(tx.clone(), src.clone(), dst, &settings, &item.msg) {
+                if e.is::<SendError<WsCommand>>() {
+                    // the send listener is gone
+                    return;
+                }
+                eprintln!("Could not send scheduled message {}: {}", item.id, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_relative_times() {
+        let before = unix_now();
+        let at = parse_time("+1h").unwrap();
+        assert!(at >= before + 3600 && at <= unix_now() + 3600);
+    }
+
+    #[test]
+    fn parses_absolute_times() {
+        let expected = Local.ymd(2030, 1, 2).and_hms(3, 4, 0).timestamp() as u64;
+        assert_eq!(parse_time("2030-01-02 03:04").unwrap(), expected);
+        assert_eq!(parse_time("2030-01-02T03:04").unwrap(), expected);
+        assert_eq!(parse_time("2030-01-02 03:04:05").unwrap(), expected + 5);
+    }
+
+    #[test]
+    fn clock_times_are_within_the_next_day() {
+        let now = unix_now();
+        for clock in &["00:00", "12:30", "23:59:59"] {
+            let at = parse_time(clock).unwrap();
+            assert!(at > now - 1 && at <= now + 24 * 3600, "{}", clock);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_times() {
+        assert!(parse_time("tomorrow").is_err());
+        assert!(parse_time("25:00").is_err());
+        assert!(parse_time("+soon").is_err());
+        assert!(parse_time("").is_err());
+    }
+
+    #[test]
+    fn recognises_clock_times() {
+        assert!(is_clock_time("12:30"));
+        assert!(is_clock_time("9:05"));
+        assert!(is_clock_time("1:05:09"));
+        assert!(!is_clock_time("12"));
+        assert!(!is_clock_time("12:3a"));
+        assert!(!is_clock_time("123:00"));
+        assert!(!is_clock_time("12:00:00:00"));
+        assert!(!is_clock_time("12:"));
+        assert!(!is_clock_time("bob"));
+    }
+}
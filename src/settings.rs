@@ -0,0 +1,369 @@
+use crate::options::{on_off, BundleOptions, Reports};
+use crate::theme::{self, Theme};
+use crate::timefmt::TimeFormat;
+use anyhow::{bail, Result};
+use bp7::EndpointID;
+use humantime::{format_duration, parse_duration};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Settings saved by `/save-settings`, one `name value` pair per line
+///
+/// Besides [`NAMES`] the file holds `bundle <options>`, `conversation <peer>
+/// <options>` and `encoding <peer> <encoding>` lines.
+pub const SETTINGS_FILE: &str = "dtnchat.settings";
+
+/// Names accepted by `/set` and `/get`
+pub const NAMES: &[&str] = &[
+    "lifetime",
+    "part-size",
+    "time-format",
+    "show-delay",
+    "verbose",
+    "compression",
+    "delivery-notifications",
+    "auto-save-history",
+    "dump-bundles",
+    "persist-stats",
+    "theme",
+];
+
+const ON_OFF: &[&str] = &["on", "off"];
+
+/// Payload format of chat messages
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Encoding {
+    /// dtn7-plus SMS, required for dtnchat features like replies and long messages
+    #[default]
+    Sms,
+    /// Raw UTF-8 text as sent by bpchat, dtnsend and similar tools
+    Plain,
+}
+
+impl FromStr for Encoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "sms" => Ok(Encoding::Sms),
+            "plain" => Ok(Encoding::Plain),
+            _ => bail!("unknown encoding {}, use sms or plain", s),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Sms => write!(f, "sms"),
+            Encoding::Plain => write!(f, "plain"),
+        }
+    }
+}
+
+/// Default maximum size of a single message part in bytes
+pub const DEFAULT_PART_SIZE: usize = 1024;
+
+/// Settings for sending and showing messages
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub lifetime: Duration,
+    /// Longer messages are split into multiple parts
+    pub part_size: usize,
+    pub time_format: TimeFormat,
+    /// Show receive time and end-to-end delay next to the creation time
+    pub show_delay: bool,
+    /// Print notices about control messages, reports and sent bundles
+    pub verbose: bool,
+    /// Compress the text of outgoing SMS
+    pub compression: bool,
+    /// Save the input history when quitting
+    pub auto_save_history: bool,
+    /// Print all blocks of every received bundle
+    pub dump_bundles: bool,
+    /// Keep the `/stats` counters across restarts
+    pub persist_stats: bool,
+    pub theme: Theme,
+    /// Built-in theme name or JSON object `theme` was set from
+    pub theme_name: String,
+    /// Options for chat messages
+    pub bundle: BundleOptions,
+    /// Options overriding `bundle` for single peers or groups
+    pub conversations: HashMap<String, BundleOptions>,
+    /// Conversations not using [`Encoding::Sms`], by endpoint or node
+    pub encodings: HashMap<String, Encoding>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            lifetime: Duration::from_secs(60 * 60),
+            part_size: DEFAULT_PART_SIZE,
+            time_format: TimeFormat::Local,
+            show_delay: false,
+            verbose: false,
+            compression: true,
+            auto_save_history: false,
+            dump_bundles: false,
+            persist_stats: false,
+            theme: Theme::default(),
+            theme_name: "default".to_string(),
+            bundle: BundleOptions {
+                reports: Reports::delivery(),
+                ..Default::default()
+            },
+            conversations: HashMap::new(),
+            encodings: HashMap::new(),
+        }
+    }
+}
+
+fn show_on_off(value: bool) -> String {
+    if value { "on" } else { "off" }.to_string()
+}
+
+impl Settings {
+    /// Apply the settings saved in [`SETTINGS_FILE`] if there are any
+    pub fn load(&mut self) -> Result<()> {
+        self.load_from(Path::new(SETTINGS_FILE))
+    }
+    fn load_from(&mut self, path: &Path) -> Result<()> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            if let Err(e) = self.load_line(name, value.trim()) {
+                eprintln!(
+                    "Skipping line {} of settings {}: {}",
+                    n + 1,
+                    path.display(),
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+    fn load_line(&mut self, name: &str, value: &str) -> Result<()> {
+        match name {
+            "bundle" => self.bundle.set_all(value)?,
+            "conversation" => {
+                let (peer, options) = value.split_once(' ').unwrap_or((value, ""));
+                let mut bundle = self.bundle.clone();
+                bundle.set_all(options)?;
+                self.conversations.insert(peer.to_string(), bundle);
+            }
+            "encoding" => match value.split_once(' ') {
+                Some((peer, encoding)) => {
+                    self.encodings.insert(peer.to_string(), encoding.parse()?);
+                }
+                None => bail!("expected encoding <peer> <encoding>"),
+            },
+            _ => self.set(name, value)?,
+        }
+        Ok(())
+    }
+    pub fn save(&self) -> Result<()> {
+        self.save_to(Path::new(SETTINGS_FILE))
+    }
+    fn save_to(&self, path: &Path) -> Result<()> {
+        let mut content = String::new();
+        for name in NAMES {
+            if let Some(value) = self.get(name) {
+                content.push_str(&format!("{} {}\n", name, value));
+            }
+        }
+        content.push_str(&format!("bundle {}\n", self.bundle));
+        for (peer, options) in &self.conversations {
+            content.push_str(&format!("conversation {} {}\n", peer, options));
+        }
+        for (peer, encoding) in &self.encodings {
+            content.push_str(&format!("encoding {} {}\n", peer, encoding));
+        }
+        fs::write(path, content)?;
+        Ok(())
+    }
+    pub fn get(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "lifetime" => format_duration(self.lifetime).to_string(),
+            "part-size" => self.part_size.to_string(),
+            "time-format" => self.time_format.to_string(),
+            "show-delay" => show_on_off(self.show_delay),
+            "verbose" => show_on_off(self.verbose),
+            "compression" => show_on_off(self.compression),
+            "delivery-notifications" => show_on_off(self.bundle.reports.delivery),
+            "auto-save-history" => show_on_off(self.auto_save_history),
+            "dump-bundles" => show_on_off(self.dump_bundles),
+            "persist-stats" => show_on_off(self.persist_stats),
+            "theme" => self.theme_name.clone(),
+            _ => return None,
+        };
+        Some(value)
+    }
+    /// Change the setting `name`, `value` as entered by the user
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        match name {
+            "lifetime" => self.lifetime = parse_duration(value)?,
+            "part-size" => match value.parse::<usize>() {
+                Ok(size) if size >= 16 => self.part_size = size,
+                _ => bail!("invalid part size, must be at least 16 bytes"),
+            },
+            "time-format" => self.time_format = value.parse()?,
+            "show-delay" => self.show_delay = on_off(value)?,
+            "verbose" => self.verbose = on_off(value)?,
+            "compression" => self.compression = on_off(value)?,
+            "delivery-notifications" => self.bundle.reports.delivery = on_off(value)?,
+            "auto-save-history" => self.auto_save_history = on_off(value)?,
+            "dump-bundles" => self.dump_bundles = on_off(value)?,
+            "persist-stats" => self.persist_stats = on_off(value)?,
+            "theme" => {
+                // a JSON object overrides single colours of the default theme
+                let theme = if value.starts_with('{') {
+                    serde_json::from_str(value)?
+                } else {
+                    Theme::builtin(value)?
+                };
+                self.theme = if theme::colour_enabled() {
+                    theme
+                } else {
+                    theme.without_colour()
+                };
+                self.theme_name = value.to_string();
+            }
+            _ => bail!("unknown setting {}", name),
+        }
+        Ok(())
+    }
+    /// Payload format for messages sent to `dst`
+    pub fn encoding_for(&self, dst: &EndpointID) -> Encoding {
+        self.encodings
+            .get(&dst.to_string())
+            .or_else(|| dst.node().and_then(|node| self.encodings.get(&node)))
+            .copied()
+            .unwrap_or_default()
+    }
+    /// Suggested values of a setting for completion
+    pub fn values(name: &str) -> &'static [&'static str] {
+        match name {
+            "lifetime" => &["10m", "1h", "1d", "7d"],
+            "part-size" => &["256", "1024", "4096"],
+            "time-format" => &["local", "utc", "iso", "relative"],
+            "show-delay"
+            | "verbose"
+            | "compression"
+            | "delivery-notifications"
+            | "auto-save-history"
+            | "dump-bundles"
+            | "persist-stats" => ON_OFF,
+            "theme" => theme::THEMES,
+            _ => &[],
+        }
+    }
+    /// Bundle options for chat messages sent to `dst`
+    pub fn options_for(&self, dst: &EndpointID) -> BundleOptions {
+        dst.node()
+            .and_then(|node| self.conversations.get(&node))
+            .unwrap_or(&self.bundle)
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "dtnchat-test-{}-{}.settings",
+            std::process::id(),
+            name
+        ))
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut settings = Settings::default();
+        settings.set("lifetime", "2h").unwrap();
+        settings.set("part-size", "256").unwrap();
+        settings.set("time-format", "utc").unwrap();
+        settings.set("show-delay", "on").unwrap();
+        settings.set("theme", "light").unwrap();
+        settings.bundle.set("hop-limit", "5").unwrap();
+        let mut group = settings.bundle.clone();
+        group.set_all("reports=none crc=16").unwrap();
+        settings.conversations.insert("group1".to_string(), group);
+        settings
+            .encodings
+            .insert("dtn://bpchat/chat".to_string(), Encoding::Plain);
+
+        let path = temp_path("round-trip");
+        settings.save_to(&path).unwrap();
+        let mut loaded = Settings::default();
+        loaded.load_from(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        for name in NAMES {
+            assert_eq!(loaded.get(name), settings.get(name), "{}", name);
+        }
+        assert_eq!(loaded.bundle, settings.bundle);
+        assert_eq!(loaded.conversations, settings.conversations);
+        assert_eq!(loaded.encodings, settings.encodings);
+    }
+
+    #[test]
+    fn load_skips_bad_lines() {
+        let path = temp_path("bad-lines");
+        fs::write(
+            &path,
+            "lifetime 2h\nlifetime soon\nunknown 1\nencoding nobody\npart-size 256\n",
+        )
+        .unwrap();
+        let mut settings = Settings::default();
+        settings.load_from(&path).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(settings.lifetime, Duration::from_secs(2 * 60 * 60));
+        assert_eq!(settings.part_size, 256);
+        assert!(settings.encodings.is_empty());
+    }
+
+    #[test]
+    fn missing_file_keeps_defaults() {
+        let mut settings = Settings::default();
+        settings.load_from(&temp_path("missing")).unwrap();
+        assert_eq!(settings.part_size, DEFAULT_PART_SIZE);
+    }
+
+    #[test]
+    fn theme_from_json() {
+        let mut settings = Settings::default();
+        settings.set("theme", r#"{"accessible": true}"#).unwrap();
+        assert!(settings.theme.accessible);
+        assert!(settings.set("theme", "neon").is_err());
+        assert!(settings.theme.accessible);
+    }
+
+    #[test]
+    fn encoding_by_endpoint_or_node() {
+        let mut settings = Settings::default();
+        settings
+            .encodings
+            .insert("bob".to_string(), Encoding::Plain);
+        let bob: EndpointID = "dtn://bob/sms".try_into().unwrap();
+        let alice: EndpointID = "dtn://alice/sms".try_into().unwrap();
+        assert_eq!(settings.encoding_for(&bob), Encoding::Plain);
+        assert_eq!(settings.encoding_for(&alice), Encoding::Sms);
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use bp7::Bundle;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// File extension used for bundles in the spool directory
+pub const SPOOL_EXT: &str = "bundle";
+/// Ids of all bundles already spooled or imported, one per line
+pub const SEEN_FILE: &str = "dtnchat.seen";
+
+/// Turn a bundle id into something usable as a file name
+fn file_name(bid: &str) -> String {
+    let name: String = bid
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{}.{}", name, SPOOL_EXT)
+}
+
+/// Write a serialized bundle into the spool directory
+pub fn store(dir: &Path, bid: &str, cbor: &[u8]) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(file_name(bid));
+    fs::write(&path, cbor)?;
+    mark_seen(bid)?;
+    Ok(path)
+}
+
+/// Read all `.bundle` files from a directory
+///
+/// Files that cannot be decoded are returned as errors so the caller can report them.
+pub fn load_dir(dir: &Path) -> Result<Vec<(PathBuf, Result<Bundle>)>> {
+    let mut bundles = Vec::new();
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some(SPOOL_EXT))
+        .collect();
+    entries.sort();
+    for path in entries {
+        let bndl = fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|buf| Bundle::try_from(buf).map_err(anyhow::Error::from));
+        bundles.push((path, bndl));
+    }
+    Ok(bundles)
+}
+
+/// Load the set of bundle ids that have already been spooled or imported
+pub fn seen() -> Result<HashSet<String>> {
+    match fs::File::open(SEEN_FILE) {
+        Ok(f) => {
+            let mut ids = HashSet::new();
+            for line in BufReader::new(f).lines() {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    ids.insert(line.trim().to_string());
+                }
+            }
+            Ok(ids)
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Remember a bundle id so later imports skip it
+pub fn mark_seen(bid: &str) -> Result<()> {
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(SEEN_FILE)?;
+    writeln!(f, "{}", bid)?;
+    Ok(())
+}
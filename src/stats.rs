@@ -0,0 +1,316 @@
+use crate::clock::dtn_to_unix;
+use crate::ping::unix_now_ms;
+use crate::roster::unix_now;
+use crate::settings::Settings;
+use anyhow::Result;
+use bp7::dtntime::{DtnTime, SECONDS1970_TO2K};
+use bp7::flags::{BundleControlFlags, BundleValidation};
+use bp7::Bundle;
+use humantime::format_duration;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Counters kept across restarts as JSON if `persist-stats` is on
+pub const STATS_FILE: &str = "dtnchat.stats";
+
+/// How often the counters are saved while `persist-stats` is on
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Delay samples kept per peer, older ones are dropped
+const MAX_DELAYS: usize = 10_000;
+
+/// Upper bounds in milliseconds of the delivery delay histogram buckets
+pub const DELAY_BUCKETS_MS: &[u64] = &[
+    1_000,
+    10_000,
+    60_000,
+    10 * 60_000,
+    60 * 60_000,
+    6 * 60 * 60_000,
+    24 * 60 * 60_000,
+];
+
+/// Delay distribution that, unlike the samples, is never truncated
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Histogram {
+    /// Observations per bucket of [`DELAY_BUCKETS_MS`], not cumulative
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub sum_ms: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, ms: u64) {
+        self.buckets.resize(DELAY_BUCKETS_MS.len(), 0);
+        if let Some(i) = DELAY_BUCKETS_MS.iter().position(|b| ms <= *b) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum_ms += ms;
+    }
+}
+
+/// Unix time in milliseconds of a DTN time, `None` for senders without or with a broken clock
+fn unix_ms(time: DtnTime) -> Option<u64> {
+    if time == 0 || dtn_to_unix(time).is_none() {
+        None
+    } else {
+        Some(time + SECONDS1970_TO2K * 1000)
+    }
+}
+
+fn push_delay(delays: &mut VecDeque<u64>, delay: u64) {
+    if delays.len() >= MAX_DELAYS {
+        delays.pop_front();
+    }
+    delays.push_back(delay);
+}
+
+/// Minimum, median and maximum of delays in milliseconds
+pub fn delay_stats<'a>(delays: impl IntoIterator<Item = &'a u64>) -> Option<(u64, u64, u64)> {
+    let mut sorted: Vec<u64> = delays.into_iter().copied().collect();
+    sorted.sort_unstable();
+    Some((*sorted.first()?, sorted[sorted.len() / 2], *sorted.last()?))
+}
+
+fn format_delays(delays: &VecDeque<u64>) -> String {
+    match delay_stats(delays) {
+        Some((min, median, max)) => format!(
+            "min {}, median {}, max {} ({} samples)",
+            format_duration(Duration::from_millis(min)),
+            format_duration(Duration::from_millis(median)),
+            format_duration(Duration::from_millis(max)),
+            delays.len()
+        ),
+        None => "no samples".to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Counters {
+    pub bundles_sent: u64,
+    pub bytes_sent: u64,
+    pub messages_sent: u64,
+    pub bundles_received: u64,
+    pub bytes_received: u64,
+    pub messages_received: u64,
+    /// Sent bundles asking for a delivery report
+    pub reports_requested: u64,
+    pub delivered: u64,
+    /// Milliseconds from creation of sent bundles to their delivery
+    pub delivery_delays: VecDeque<u64>,
+    /// Milliseconds from creation of received bundles to their arrival
+    pub receive_delays: VecDeque<u64>,
+    #[serde(default)]
+    pub delivery_histogram: Histogram,
+}
+
+impl Counters {
+    /// Percentage of bundles asking for a delivery report that were delivered
+    pub fn delivery_ratio(&self) -> Option<f64> {
+        if self.reports_requested == 0 {
+            return None;
+        }
+        Some(100.0 * self.delivered as f64 / self.reports_requested as f64)
+    }
+    /// Human readable summary as shown by `/stats`
+    pub fn describe(&self) -> Vec<String> {
+        vec![
+            format!(
+                "sent:           {} bundles, {} bytes, {} messages",
+                self.bundles_sent, self.bytes_sent, self.messages_sent
+            ),
+            format!(
+                "received:       {} bundles, {} bytes, {} messages",
+                self.bundles_received, self.bytes_received, self.messages_received
+            ),
+            format!(
+                "delivered:      {} of {}{}",
+                self.delivered,
+                self.reports_requested,
+                self.delivery_ratio()
+                    .map_or(String::new(), |r| format!(" ({:.1}%)", r))
+            ),
+            format!("delivery delay: {}", format_delays(&self.delivery_delays)),
+            format!("receive delay:  {}", format_delays(&self.receive_delays)),
+        ]
+    }
+}
+
+/// Sent bundle waiting for its delivery report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Pending {
+    peer: String,
+    /// Unix time in milliseconds
+    created: u64,
+    /// Unix time after which no report is expected anymore
+    expires: u64,
+}
+
+/// Traffic counters for evaluating a deployment, in total and per peer
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Stats {
+    /// Unix time of the start or the last reset
+    pub since: u64,
+    pub total: Counters,
+    pub peers: BTreeMap<String, Counters>,
+    /// Unix time the last bundle from another node arrived
+    #[serde(default)]
+    pub last_received: Option<u64>,
+    pending: HashMap<String, Pending>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats {
+            since: unix_now(),
+            ..Default::default()
+        }
+    }
+    pub fn load() -> Result<Self> {
+        match fs::read_to_string(STATS_FILE) {
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Stats::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+    pub fn save(&self) -> Result<()> {
+        fs::write(STATS_FILE, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+    pub fn reset(&mut self) {
+        *self = Stats::new();
+    }
+    /// Apply `f` to the counters of `peer` and the totals
+    fn count(&mut self, peer: &str, f: impl Fn(&mut Counters)) {
+        f(&mut self.total);
+        f(self.peers.entry(peer.to_string()).or_default());
+    }
+    /// Count a bundle handed to dtnd, `message` if it completes a chat message
+    pub fn sent(&mut self, bndl: &Bundle, size: usize, message: bool) {
+        let peer = bndl.primary.destination.node().unwrap_or_default();
+        let report = bndl
+            .primary
+            .bundle_control_flags
+            .flags()
+            .contains(BundleControlFlags::BUNDLE_STATUS_REQUEST_DELIVERY);
+        self.count(&peer, |c| {
+            c.bundles_sent += 1;
+            c.bytes_sent += size as u64;
+            c.messages_sent += message as u64;
+            c.reports_requested += report as u64;
+        });
+        let now = unix_now();
+        self.pending.retain(|_, p| p.expires > now);
+        if report {
+            self.pending.insert(
+                bndl.id(),
+                Pending {
+                    peer,
+                    created: unix_ms(bndl.primary.creation_timestamp.dtntime())
+                        .unwrap_or_else(unix_now_ms),
+                    expires: now + bndl.primary.lifetime.as_secs(),
+                },
+            );
+        }
+    }
+    /// Count a bundle from another node
+    pub fn received(&mut self, bndl: &Bundle, size: usize) {
+        let peer = bndl.primary.source.node().unwrap_or_default();
+        let delay = unix_ms(bndl.primary.creation_timestamp.dtntime())
+            .map(|created| unix_now_ms().saturating_sub(created));
+        self.count(&peer, |c| {
+            c.bundles_received += 1;
+            c.bytes_received += size as u64;
+            if let Some(delay) = delay {
+                push_delay(&mut c.receive_delays, delay);
+            }
+        });
+        self.last_received = Some(unix_now());
+    }
+    pub fn message_received(&mut self, peer: &str) {
+        self.count(peer, |c| c.messages_received += 1);
+    }
+    /// Count the delivery report for `bid`, reported at `time` or unknown time
+    pub fn delivered(&mut self, bid: &str, time: DtnTime) {
+        let pending = match self.pending.remove(bid) {
+            Some(pending) => pending,
+            None => return,
+        };
+        let delivered = unix_ms(time).unwrap_or_else(unix_now_ms);
+        let delay = delivered.saturating_sub(pending.created);
+        self.count(&pending.peer, |c| {
+            c.delivered += 1;
+            push_delay(&mut c.delivery_delays, delay);
+            c.delivery_histogram.observe(delay);
+        });
+    }
+    /// Number of sent bundles still waiting for a delivery report
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+/// Save `stats` every [`SAVE_INTERVAL`] so a crash loses little, runs forever
+pub fn save_loop(stats: Arc<Mutex<Stats>>, settings: Arc<Mutex<Settings>>) {
+    loop {
+        thread::sleep(SAVE_INTERVAL);
+        if !settings.lock().unwrap().persist_stats {
+            continue;
+        }
+        if let Err(e) = stats.lock().unwrap().save() {
+            eprintln!("Could not save statistics {}: {}", STATS_FILE, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bp7::{CreationTimestamp, EndpointID};
+    use std::convert::TryInto;
+
+    #[test]
+    fn delay_stats_without_samples() {
+        assert_eq!(delay_stats(&[]), None);
+    }
+
+    #[test]
+    fn delay_stats_of_unsorted_samples() {
+        assert_eq!(delay_stats(&[5]), Some((5, 5, 5)));
+        assert_eq!(delay_stats(&[30, 10, 20]), Some((10, 20, 30)));
+        // the upper median for an even number of samples
+        assert_eq!(delay_stats(&[4, 1, 3, 2]), Some((1, 3, 4)));
+    }
+
+    #[test]
+    fn broken_sender_clocks_give_no_delay() {
+        let src: EndpointID = "dtn://alice/sms".try_into().unwrap();
+        let dst: EndpointID = "dtn://bob/sms".try_into().unwrap();
+        let mut bndl = bp7::bundle::new_std_payload_bundle(src, dst, b"hi".to_vec());
+        let mut stats = Stats::new();
+        for time in [0, u64::MAX] {
+            bndl.primary.creation_timestamp = CreationTimestamp::with_time_and_seq(time, 0);
+            stats.received(&bndl, 10);
+        }
+        assert_eq!(stats.total.bundles_received, 2);
+        assert!(stats.total.receive_delays.is_empty());
+        assert_eq!(unix_ms(u64::MAX), None);
+    }
+
+    #[test]
+    fn keeps_the_newest_delays() {
+        let mut delays = VecDeque::new();
+        for delay in 0..MAX_DELAYS as u64 + 2 {
+            push_delay(&mut delays, delay);
+        }
+        assert_eq!(delays.len(), MAX_DELAYS);
+        assert_eq!(delays.front(), Some(&2));
+        assert_eq!(delays.back(), Some(&(MAX_DELAYS as u64 + 1)));
+    }
+}
@@ -0,0 +1,287 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use termion::{color, style};
+
+/// Names of the built-in themes
+pub const THEMES: &[&str] = &["default", "light", "mono", "accessible"];
+
+/// Foreground colour of a theme element
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Colour {
+    /// Terminal default, no escape code
+    None,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    LightBlack,
+    LightRed,
+    LightGreen,
+    LightYellow,
+    LightBlue,
+    LightMagenta,
+    LightCyan,
+    LightWhite,
+}
+
+impl fmt::Display for Colour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Colour::None => Ok(()),
+            Colour::Black => write!(f, "{}", color::Fg(color::Black)),
+            Colour::Red => write!(f, "{}", color::Fg(color::Red)),
+            Colour::Green => write!(f, "{}", color::Fg(color::Green)),
+            Colour::Yellow => write!(f, "{}", color::Fg(color::Yellow)),
+            Colour::Blue => write!(f, "{}", color::Fg(color::Blue)),
+            Colour::Magenta => write!(f, "{}", color::Fg(color::Magenta)),
+            Colour::Cyan => write!(f, "{}", color::Fg(color::Cyan)),
+            Colour::White => write!(f, "{}", color::Fg(color::White)),
+            Colour::LightBlack => write!(f, "{}", color::Fg(color::LightBlack)),
+            Colour::LightRed => write!(f, "{}", color::Fg(color::LightRed)),
+            Colour::LightGreen => write!(f, "{}", color::Fg(color::LightGreen)),
+            Colour::LightYellow => write!(f, "{}", color::Fg(color::LightYellow)),
+            Colour::LightBlue => write!(f, "{}", color::Fg(color::LightBlue)),
+            Colour::LightMagenta => write!(f, "{}", color::Fg(color::LightMagenta)),
+            Colour::LightCyan => write!(f, "{}", color::Fg(color::LightCyan)),
+            Colour::LightWhite => write!(f, "{}", color::Fg(color::LightWhite)),
+        }
+    }
+}
+
+/// Colours picked for nicks when `nick_colours` is enabled
+const NICK_PALETTE: &[Colour] = &[
+    Colour::Green,
+    Colour::Yellow,
+    Colour::Blue,
+    Colour::Magenta,
+    Colour::Cyan,
+    Colour::LightRed,
+    Colour::LightGreen,
+    Colour::LightYellow,
+    Colour::LightBlue,
+    Colour::LightMagenta,
+    Colour::LightCyan,
+];
+
+/// Colours and labels used for all output
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// Brackets around the message header
+    pub frame: Colour,
+    pub time: Colour,
+    pub nick: Colour,
+    pub group: Colour,
+    pub notice: Colour,
+    pub error: Colour,
+    /// Quotes, reactions and other secondary information
+    pub dim: Colour,
+    /// Messages mentioning us
+    pub highlight: Colour,
+    pub prompt: Colour,
+    /// Peer of the current query in the prompt
+    pub query: Colour,
+    /// Colour nicks by a hash of their node name
+    pub nick_colours: bool,
+    /// No logo, colour codes or symbols but explicit textual labels
+    pub accessible: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            frame: Colour::LightWhite,
+            time: Colour::Cyan,
+            nick: Colour::LightGreen,
+            group: Colour::Green,
+            notice: Colour::Yellow,
+            error: Colour::Red,
+            dim: Colour::LightBlack,
+            highlight: Colour::LightYellow,
+            prompt: Colour::LightBlue,
+            query: Colour::LightCyan,
+            nick_colours: false,
+            accessible: false,
+        }
+    }
+}
+
+/// Whether colour output is wanted, see <https://no-color.org>
+pub fn colour_enabled() -> bool {
+    std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && termion::is_tty(&io::stdout())
+}
+
+impl Theme {
+    pub fn builtin(name: &str) -> Result<Theme> {
+        let theme = match name {
+            "default" => Theme::default(),
+            "light" => Theme {
+                frame: Colour::Black,
+                time: Colour::Blue,
+                nick: Colour::Green,
+                group: Colour::Magenta,
+                notice: Colour::Blue,
+                error: Colour::Red,
+                dim: Colour::LightBlack,
+                highlight: Colour::Red,
+                prompt: Colour::Blue,
+                query: Colour::Magenta,
+                nick_colours: true,
+                accessible: false,
+            },
+            "mono" => Theme::default().without_colour(),
+            "accessible" => Theme {
+                accessible: true,
+                ..Theme::default().without_colour()
+            },
+            _ => bail!("unknown theme {}, use {}", name, THEMES.join(", ")),
+        };
+        Ok(theme)
+    }
+    pub fn without_colour(self) -> Theme {
+        Theme {
+            frame: Colour::None,
+            time: Colour::None,
+            nick: Colour::None,
+            group: Colour::None,
+            notice: Colour::None,
+            error: Colour::None,
+            dim: Colour::None,
+            highlight: Colour::None,
+            prompt: Colour::None,
+            query: Colour::None,
+            nick_colours: false,
+            ..self
+        }
+    }
+    pub fn is_monochrome(&self) -> bool {
+        [
+            self.frame,
+            self.time,
+            self.nick,
+            self.group,
+            self.notice,
+            self.error,
+            self.dim,
+            self.highlight,
+            self.prompt,
+            self.query,
+        ]
+        .iter()
+        .all(|c| *c == Colour::None)
+    }
+    pub fn reset(&self) -> String {
+        if self.is_monochrome() {
+            String::new()
+        } else {
+            style::Reset.to_string()
+        }
+    }
+    /// Start of an error line
+    pub fn error(&self) -> String {
+        if self.accessible {
+            "Error: ".into()
+        } else {
+            self.error.to_string()
+        }
+    }
+    /// Start of an informational line
+    pub fn notice(&self) -> String {
+        if self.accessible {
+            "Note: ".into()
+        } else {
+            self.notice.to_string()
+        }
+    }
+    pub fn nick_colour(&self, node: &str) -> Colour {
+        if !self.nick_colours {
+            return self.nick;
+        }
+        let hash = node
+            .bytes()
+            .fold(0u32, |h, b| h.wrapping_mul(31).wrapping_add(b as u32));
+        NICK_PALETTE[hash as usize % NICK_PALETTE.len()]
+    }
+    /// Creation time followed by receive time and delay
+    pub fn delay(&self, created: &str, received: &str, delay: &str) -> String {
+        if self.accessible {
+            format!("{}, received {}, delay {}", created, received, delay)
+        } else {
+            format!("{} ⇢ {} ({})", created, received, delay)
+        }
+    }
+    /// Message header and text, `group` is `None` for direct messages
+    pub fn message(
+        &self,
+        time: &str,
+        src: &str,
+        sender: &str,
+        group: Option<&str>,
+        msg: &str,
+    ) -> String {
+        if self.accessible {
+            return match group {
+                Some(group) => format!("{} {} to {}: {}", time, sender, group, msg),
+                None => format!("{} {}: {}", time, sender, msg),
+            };
+        }
+        match group {
+            Some(group) => format!(
+                "{}[{}{} {}{} {}> {}{}{} ] {}{}",
+                self.frame,
+                self.time,
+                time,
+                self.nick_colour(src),
+                sender,
+                self.frame,
+                self.group,
+                group,
+                self.frame,
+                self.reset(),
+                msg
+            ),
+            None => format!(
+                "{}[{}{} {}{}{}] {}{}",
+                self.frame,
+                self.time,
+                time,
+                self.nick_colour(src),
+                sender,
+                self.frame,
+                self.reset(),
+                msg
+            ),
+        }
+    }
+    /// Message text mentioning us, rings the terminal bell
+    pub fn mention(&self, msg: &str) -> String {
+        if self.accessible {
+            format!("Mention! {}\x07", msg)
+        } else if self.is_monochrome() {
+            format!("{}\x07", msg)
+        } else {
+            format!(
+                "{}{}{}{}\x07",
+                style::Bold,
+                self.highlight,
+                msg,
+                self.reset()
+            )
+        }
+    }
+    /// Line shown above a reply
+    pub fn quote(&self, sender: &str, text: &str) -> String {
+        if self.accessible {
+            format!("  In reply to {}: {}", sender, text)
+        } else {
+            format!("{}  ╭ {}: {}{}", self.dim, sender, text, self.reset())
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::clock::MAX_UNIX_TIME;
+use crate::roster::unix_now;
+use anyhow::bail;
+use chrono::{DateTime, Local, TimeZone, Utc};
+use humantime::format_duration;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How timestamps of messages are shown
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeFormat {
+    Local,
+    Utc,
+    Iso,
+    /// Age of the message, e.g. `3h ago`
+    Relative,
+}
+
+impl FromStr for TimeFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "local" => Ok(TimeFormat::Local),
+            "utc" => Ok(TimeFormat::Utc),
+            "iso" => Ok(TimeFormat::Iso),
+            "relative" => Ok(TimeFormat::Relative),
+            _ => bail!("unknown time format {}, use local, utc, iso or relative", s),
+        }
+    }
+}
+
+impl fmt::Display for TimeFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            TimeFormat::Local => "local",
+            TimeFormat::Utc => "utc",
+            TimeFormat::Iso => "iso",
+            TimeFormat::Relative => "relative",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Coarse human readable age, only the largest unit is shown
+pub fn ago(unixtime: u64) -> String {
+    let now = unix_now();
+    let (secs, future) = if unixtime > now {
+        (unixtime - now, true)
+    } else {
+        (now - unixtime, false)
+    };
+    let age = match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    };
+    if future {
+        format!("in {}", age)
+    } else {
+        format!("{} ago", age)
+    }
+}
+
+/// Local date and time, times beyond [`MAX_UNIX_TIME`] are shown as that
+pub fn local_time(unixtime: u64) -> DateTime<Local> {
+    Local.timestamp(unixtime.min(MAX_UNIX_TIME) as i64, 0)
+}
+
+pub fn format_time(unixtime: u64, format: TimeFormat) -> String {
+    match format {
+        TimeFormat::Local => local_time(unixtime).format("%F %T").to_string(),
+        TimeFormat::Utc => Utc
+            .timestamp(unixtime.min(MAX_UNIX_TIME) as i64, 0)
+            .format("%F %T UTC")
+            .to_string(),
+        TimeFormat::Iso => local_time(unixtime).to_rfc3339(),
+        TimeFormat::Relative => ago(unixtime),
+    }
+}
+
+/// End-to-end delay between creation and reception
+pub fn format_delay(created: u64, received: u64) -> String {
+    if received >= created {
+        format_duration(Duration::from_secs(received - created)).to_string()
+    } else {
+        format!(
+            "-{}",
+            format_duration(Duration::from_secs(created - received))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_utc_times() {
+        assert_eq!(format_time(0, TimeFormat::Utc), "1970-01-01 00:00:00 UTC");
+        assert_eq!(
+            format_time(1_600_000_000, TimeFormat::Utc),
+            "2020-09-13 12:26:40 UTC"
+        );
+    }
+
+    #[test]
+    fn clamps_times_beyond_year_9999() {
+        assert_eq!(
+            format_time(u64::MAX, TimeFormat::Utc),
+            "9999-12-31 23:59:59 UTC"
+        );
+        // must not panic for any format
+        for format in &[TimeFormat::Local, TimeFormat::Iso, TimeFormat::Relative] {
+            format_time(u64::MAX, *format);
+        }
+    }
+
+    #[test]
+    fn formats_relative_times() {
+        let now = unix_now();
+        assert_eq!(format_time(now - 2 * 3600, TimeFormat::Relative), "2h ago");
+        assert_eq!(format_time(now + 3 * 86400, TimeFormat::Relative), "in 3d");
+    }
+
+    #[test]
+    fn formats_delays() {
+        assert_eq!(format_delay(10, 10), "0s");
+        assert_eq!(format_delay(10, 75), "1m 5s");
+        // clocks of sender and receiver disagree
+        assert_eq!(format_delay(75, 10), "-1m 5s");
+    }
+
+    #[test]
+    fn parses_time_formats() {
+        for format in &["local", "utc", "iso", "relative"] {
+            assert_eq!(&format.parse::<TimeFormat>().unwrap().to_string(), format);
+        }
+        assert!("gmt".parse::<TimeFormat>().is_err());
+    }
+}
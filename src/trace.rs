@@ -0,0 +1,129 @@
+use crate::clock::dtn_to_unix;
+use crate::roster::unix_now;
+use bp7::administrative_record::{
+    StatusReport, DELIVERED_BUNDLE, FORWARDED_BUNDLE, RECEIVED_BUNDLE,
+};
+use bp7::flags::BundleControlFlags;
+use bp7::{Bundle, EndpointID};
+use std::time::Duration;
+
+/// Node on the path of a trace probe with the unix times it reported
+#[derive(Debug, Clone)]
+pub struct Hop {
+    pub node: String,
+    pub received: Option<u64>,
+    pub forwarded: Option<u64>,
+    pub delivered: Option<u64>,
+}
+
+impl Hop {
+    /// Earliest time reported by this node, used to order the path
+    fn first_seen(&self) -> u64 {
+        [self.received, self.forwarded, self.delivered]
+            .iter()
+            .flatten()
+            .copied()
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+}
+
+#[derive(Debug)]
+pub struct Trace {
+    /// Bundle id of the probe
+    pub bid: String,
+    pub peer: String,
+    /// Unix time the probe was sent
+    pub started: u64,
+    /// Reporting nodes in path order
+    pub hops: Vec<Hop>,
+}
+
+/// Probes sent by `/trace` and the status reports received for them
+#[derive(Debug, Default)]
+pub struct Traces {
+    traces: Vec<Trace>,
+}
+
+impl Traces {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    pub fn start(&mut self, bid: &str, peer: &str) {
+        self.traces.push(Trace {
+            bid: bid.to_string(),
+            peer: peer.to_string(),
+            started: unix_now(),
+            hops: Vec::new(),
+        });
+    }
+    /// Add a status report sent by `node`, `None` if it is not about a trace probe
+    pub fn report(&mut self, node: &str, report: &StatusReport) -> Option<(&Trace, Hop)> {
+        let bid = report.refbundle();
+        let trace = self.traces.iter_mut().find(|t| t.bid == bid)?;
+        // reports without status time get the time they arrived
+        let time = |pos: u32| {
+            report
+                .status_information
+                .get(pos as usize)
+                .filter(|i| i.asserted)
+                .map(|i| {
+                    if i.time == 0 {
+                        unix_now()
+                    } else {
+                        dtn_to_unix(i.time).unwrap_or_else(unix_now)
+                    }
+                })
+        };
+        let pos = match trace.hops.iter().position(|h| h.node == node) {
+            Some(pos) => pos,
+            None => {
+                trace.hops.push(Hop {
+                    node: node.to_string(),
+                    received: None,
+                    forwarded: None,
+                    delivered: None,
+                });
+                trace.hops.len() - 1
+            }
+        };
+        let hop = &mut trace.hops[pos];
+        hop.received = time(RECEIVED_BUNDLE).or(hop.received);
+        hop.forwarded = time(FORWARDED_BUNDLE).or(hop.forwarded);
+        hop.delivered = time(DELIVERED_BUNDLE).or(hop.delivered);
+        let hop = hop.clone();
+        trace.hops.sort_by_key(Hop::first_seen);
+        Some((trace, hop))
+    }
+    pub fn traces(&self) -> &[Trace] {
+        &self.traces
+    }
+}
+
+/// Build a probe asking every node on the way for reception and forwarding reports
+pub fn trace_bundle(
+    src: EndpointID,
+    dst: EndpointID,
+    lifetime: Duration,
+    payload: Vec<u8>,
+) -> Bundle {
+    let flags = BundleControlFlags::BUNDLE_STATUS_REQUEST_RECEPTION
+        | BundleControlFlags::BUNDLE_STATUS_REQUEST_FORWARD
+        | BundleControlFlags::BUNDLE_STATUS_REQUEST_DELIVERY
+        | BundleControlFlags::BUNDLE_REQUEST_STATUS_TIME;
+    bp7::bundle::BundleBuilder::new()
+        .primary(
+            bp7::primary::PrimaryBlockBuilder::new()
+                .source(src.clone())
+                .destination(dst)
+                .report_to(src)
+                .lifetime(lifetime)
+                .bundle_control_flags(flags.bits())
+                .creation_timestamp(bp7::CreationTimestamp::now())
+                .build()
+                .unwrap(),
+        )
+        .payload(payload)
+        .build()
+        .unwrap()
+}
@@ -0,0 +1,576 @@
+use crate::clock::creation_time;
+use crate::proto::{Action, Annotation};
+use anyhow::Result;
+use bp7::Bundle;
+use dtn7_plus::sms::SMSBundle;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Annotations kept for messages that have not arrived yet
+pub const MAX_BUFFERED: usize = 256;
+/// Buffered annotations are dropped after this time even if the bundle lives longer
+pub const MAX_BUFFER_TIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// All sent and received SMS, one JSON object per line
+///
+/// The file is only ever appended to, later changes of an entry follow it as
+/// [`Update`] lines and are replayed on load.
+pub const TRANSCRIPT_FILE: &str = "dtnchat.transcript";
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryState {
+    /// Sent by us, no report yet
+    Pending,
+    /// Delivery reported by the destination
+    Delivered,
+    /// A node reported deleting the bundle
+    Deleted,
+    /// Received from another node
+    #[default]
+    Received,
+}
+
+impl std::fmt::Display for DeliveryState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            DeliveryState::Pending => "pending",
+            DeliveryState::Delivered => "delivered",
+            DeliveryState::Deleted => "deleted",
+            DeliveryState::Received => "received",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Message a reply refers to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Quote {
+    pub bid: String,
+    pub src: String,
+    pub text: String,
+}
+
+/// Primary block fields kept for the JSON export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleMeta {
+    pub source: String,
+    pub destination: String,
+    pub report_to: String,
+    /// Bundle processing control flags
+    pub flags: u64,
+    /// Sequence number of the creation timestamp
+    pub sequence: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub bid: String,
+    /// Unix time of the bundle creation
+    pub time: u64,
+    /// Lifetime of the bundle in seconds
+    pub lifetime: u64,
+    pub src: String,
+    pub dst: String,
+    pub outgoing: bool,
+    pub msg: String,
+    #[serde(default)]
+    pub state: DeliveryState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote: Option<Quote>,
+    /// Node and reaction
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reactions: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub edited: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub retracted: bool,
+    /// Sender had no clock, `time` is derived from the receive time
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub estimated: bool,
+    /// Missing for entries written by older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle: Option<BundleMeta>,
+}
+
+impl Entry {
+    pub fn new(bndl: &Bundle, outgoing: bool, msg: String) -> Entry {
+        let (time, estimated) = creation_time(bndl);
+        Entry {
+            bid: bndl.id(),
+            time,
+            lifetime: bndl.primary.lifetime.as_secs(),
+            src: bndl.primary.source.node().unwrap_or_default(),
+            dst: bndl.primary.destination.node().unwrap_or_default(),
+            outgoing,
+            msg,
+            state: if outgoing {
+                DeliveryState::Pending
+            } else {
+                DeliveryState::Received
+            },
+            quote: None,
+            reactions: Vec::new(),
+            edited: false,
+            retracted: false,
+            estimated,
+            bundle: Some(BundleMeta {
+                source: bndl.primary.source.to_string(),
+                destination: bndl.primary.destination.to_string(),
+                report_to: bndl.primary.report_to.to_string(),
+                flags: bndl.primary.bundle_control_flags,
+                sequence: bndl.primary.creation_timestamp.seqno(),
+            }),
+        }
+    }
+    pub fn from_sms(sms: &SMSBundle, outgoing: bool) -> Entry {
+        Entry::new(sms.bundle(), outgoing, sms.msg())
+    }
+}
+
+/// Filter for `/search`
+#[derive(Debug, Default)]
+pub struct Query {
+    pub text: String,
+    pub regex: Option<Regex>,
+    pub peer: Option<String>,
+    pub group: Option<String>,
+    /// Only entries created at or after this unix time
+    pub since: Option<u64>,
+}
+
+impl Query {
+    fn matches(&self, e: &Entry) -> bool {
+        if let Some(peer) = &self.peer {
+            if &e.src != peer && &e.dst != peer {
+                return false;
+            }
+        }
+        if let Some(group) = &self.group {
+            if &e.dst != group {
+                return false;
+            }
+        }
+        if let Some(since) = self.since {
+            if e.time < since {
+                return false;
+            }
+        }
+        match &self.regex {
+            Some(re) => re.is_match(&e.msg),
+            None => e.msg.to_lowercase().contains(&self.text.to_lowercase()),
+        }
+    }
+}
+
+/// Change of an earlier entry as stored in the transcript file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Update {
+    /// Bundle id of the changed entry
+    update: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    state: Option<DeliveryState>,
+    /// Annotation and the node that sent it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    annotation: Option<(String, Annotation)>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Line {
+    Entry(Entry),
+    Update(Update),
+}
+
+pub enum Applied {
+    Done,
+    /// Target not (yet) known
+    Unknown,
+    /// Only the author may amend or retract a message
+    Denied,
+}
+
+/// Annotation that arrived before the message it refers to
+#[derive(Debug)]
+pub struct Pending {
+    pub src: String,
+    pub annotation: Annotation,
+    expires: Instant,
+}
+
+#[derive(Debug, Default)]
+pub struct Transcript {
+    entries: Vec<Entry>,
+    pending: Vec<Pending>,
+    /// File changes are appended to, `None` keeps the transcript in memory only
+    file: Option<PathBuf>,
+}
+
+impl Transcript {
+    /// Transcript kept in memory only
+    pub fn new() -> Self {
+        Default::default()
+    }
+    /// Read [`TRANSCRIPT_FILE`], lines that cannot be parsed are reported and skipped
+    pub fn load() -> Result<Self> {
+        Transcript::open(TRANSCRIPT_FILE)
+    }
+    /// Read the transcript at `path` and append all further changes to it
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let mut transcript = Transcript::new();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        for (n, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(Line::Entry(entry)) => transcript.entries.push(entry),
+                Ok(Line::Update(update)) => transcript.replay(update),
+                Err(e) => eprintln!(
+                    "Skipping line {} of transcript {}: {}",
+                    n + 1,
+                    path.display(),
+                    e
+                ),
+            }
+        }
+        transcript.file = Some(path);
+        Ok(transcript)
+    }
+    fn replay(&mut self, update: Update) {
+        if let Some(state) = update.state {
+            if let Some(e) = self.entries.iter_mut().find(|e| e.bid == update.update) {
+                e.state = state;
+            }
+        }
+        if let Some((src, annotation)) = update.annotation {
+            self.annotate(&src, &annotation);
+        }
+    }
+    fn write_line<T: Serialize>(&self, line: &T) -> Result<()> {
+        let path = match &self.file {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(f, "{}", serde_json::to_string(line)?)?;
+        Ok(())
+    }
+    /// Add an entry and append it to the transcript file
+    pub fn append(&mut self, entry: Entry) -> Result<()> {
+        self.write_line(&entry)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+    /// Update the delivery state of a sent message, returns false for unknown bundles
+    pub fn set_state(&mut self, bid: &str, state: DeliveryState) -> Result<bool> {
+        match self.entries.iter_mut().find(|e| e.bid == bid && e.outgoing) {
+            Some(e) if e.state != state => {
+                e.state = state;
+                self.write_line(&Update {
+                    update: bid.to_string(),
+                    state: Some(state),
+                    annotation: None,
+                })?;
+                Ok(true)
+            }
+            Some(_) => Ok(true),
+            None => Ok(false),
+        }
+    }
+    /// Change the entry in memory only
+    fn annotate(&mut self, src: &str, annotation: &Annotation) -> Applied {
+        let entry = match self.entries.iter_mut().find(|e| e.bid == annotation.target) {
+            Some(entry) => entry,
+            None => return Applied::Unknown,
+        };
+        match &annotation.action {
+            Action::React(reaction) => {
+                entry.reactions.push((src.to_string(), reaction.clone()));
+            }
+            Action::Amend(_) | Action::Retract if entry.src != src => {
+                return Applied::Denied;
+            }
+            Action::Amend(text) => {
+                entry.msg = text.clone();
+                entry.edited = true;
+            }
+            Action::Retract => {
+                entry.retracted = true;
+            }
+        }
+        Applied::Done
+    }
+    /// Apply a reaction, correction or retraction sent by `src`
+    pub fn apply(&mut self, src: &str, annotation: &Annotation) -> Result<Applied> {
+        let applied = self.annotate(src, annotation);
+        if let Applied::Done = applied {
+            self.write_line(&Update {
+                update: annotation.target.clone(),
+                state: None,
+                annotation: Some((src.to_string(), annotation.clone())),
+            })?;
+        }
+        Ok(applied)
+    }
+    /// Keep an annotation for a message that has not arrived yet
+    pub fn buffer(&mut self, src: &str, annotation: Annotation, lifetime: Duration) {
+        let now = Instant::now();
+        self.pending.retain(|p| p.expires > now);
+        if self.pending.len() >= MAX_BUFFERED {
+            self.pending.remove(0);
+        }
+        self.pending.push(Pending {
+            src: src.to_string(),
+            annotation,
+            expires: now + lifetime.min(MAX_BUFFER_TIME),
+        });
+    }
+    /// Take all buffered annotations for `bid`, expired ones are dropped
+    pub fn take_pending(&mut self, bid: &str) -> Vec<Pending> {
+        let now = Instant::now();
+        self.pending.retain(|p| p.expires > now);
+        let (matching, rest) = self
+            .pending
+            .drain(..)
+            .partition(|p| p.annotation.target == bid);
+        self.pending = rest;
+        matching
+    }
+    pub fn get(&self, bid: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.bid == bid)
+    }
+    /// Entries sent to or received from `peer`, `all` selects everything
+    ///
+    /// Retracted messages are left out so exports do not leak them.
+    pub fn conversation(&self, peer: &str) -> Vec<&Entry> {
+        self.entries
+            .iter()
+            .filter(|e| !e.retracted && (peer == "all" || e.src == peer || e.dst == peer))
+            .collect()
+    }
+    pub fn search(&self, query: &Query) -> Vec<&Entry> {
+        self.entries
+            .iter()
+            .filter(|e| !e.retracted && query.matches(e))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn entry(bid: &str, src: &str, outgoing: bool, msg: &str) -> Entry {
+        Entry {
+            bid: bid.to_string(),
+            time: 1_600_000_000,
+            lifetime: 3600,
+            src: src.to_string(),
+            dst: "me".to_string(),
+            outgoing,
+            msg: msg.to_string(),
+            state: if outgoing {
+                DeliveryState::Pending
+            } else {
+                DeliveryState::Received
+            },
+            quote: None,
+            reactions: Vec::new(),
+            edited: false,
+            retracted: false,
+            estimated: false,
+            bundle: None,
+        }
+    }
+
+    /// Transcript file in the temp directory, removed when dropped
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            let path = std::env::temp_dir().join(format!(
+                "dtnchat-test-{}-{}.transcript",
+                std::process::id(),
+                name
+            ));
+            let _ = fs::remove_file(&path);
+            TempFile(path)
+        }
+        fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn load_replays_updates() {
+        let file = TempFile::new("replay");
+        let mut transcript = Transcript::open(file.path()).unwrap();
+        transcript.append(entry("b1", "me", true, "hi")).unwrap();
+        transcript
+            .append(entry("b2", "alice", false, "hello"))
+            .unwrap();
+        transcript
+            .set_state("b1", DeliveryState::Delivered)
+            .unwrap();
+        let amend = Annotation {
+            target: "b2".to_string(),
+            action: Action::Amend("hello there".to_string()),
+        };
+        assert!(matches!(
+            transcript.apply("alice", &amend).unwrap(),
+            Applied::Done
+        ));
+
+        let loaded = Transcript::open(file.path()).unwrap();
+        assert_eq!(loaded.entries.len(), 2);
+        assert_eq!(loaded.get("b1").unwrap().state, DeliveryState::Delivered);
+        let b2 = loaded.get("b2").unwrap();
+        assert_eq!(b2.msg, "hello there");
+        assert!(b2.edited);
+    }
+
+    #[test]
+    fn load_skips_unreadable_lines() {
+        let file = TempFile::new("skip");
+        let mut transcript = Transcript::open(file.path()).unwrap();
+        transcript
+            .append(entry("b1", "alice", false, "one"))
+            .unwrap();
+        let mut f = OpenOptions::new().append(true).open(file.path()).unwrap();
+        writeln!(f, "{{not json").unwrap();
+        transcript
+            .append(entry("b2", "alice", false, "two"))
+            .unwrap();
+
+        let loaded = Transcript::open(file.path()).unwrap();
+        assert_eq!(loaded.entries.len(), 2);
+        // the file is kept and only appended to
+        assert_eq!(fs::read_to_string(file.path()).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    fn in_memory_transcript_writes_nothing() {
+        let mut transcript = Transcript::new();
+        transcript
+            .append(entry("b1", "alice", false, "hi"))
+            .unwrap();
+        assert!(transcript.file.is_none());
+        assert_eq!(transcript.conversation("alice").len(), 1);
+    }
+
+    fn annotation(target: &str, action: Action) -> Annotation {
+        Annotation {
+            target: target.to_string(),
+            action,
+        }
+    }
+
+    #[test]
+    fn buffered_annotation_applies_when_target_arrives() {
+        let mut transcript = Transcript::new();
+        let react = annotation("b1", Action::React("+1".to_string()));
+        assert!(matches!(
+            transcript.apply("bob", &react).unwrap(),
+            Applied::Unknown
+        ));
+        transcript.buffer("bob", react, Duration::from_secs(60));
+        assert!(transcript.take_pending("other").is_empty());
+
+        transcript
+            .append(entry("b1", "alice", false, "hi"))
+            .unwrap();
+        let pending = transcript.take_pending("b1");
+        assert_eq!(pending.len(), 1);
+        for p in &pending {
+            assert!(matches!(
+                transcript.apply(&p.src, &p.annotation).unwrap(),
+                Applied::Done
+            ));
+        }
+        assert_eq!(
+            transcript.get("b1").unwrap().reactions,
+            vec![("bob".to_string(), "+1".to_string())]
+        );
+        assert!(transcript.take_pending("b1").is_empty());
+    }
+
+    #[test]
+    fn buffer_is_bounded_and_expires() {
+        let mut transcript = Transcript::new();
+        for i in 0..MAX_BUFFERED + 1 {
+            let target = format!("b{}", i);
+            transcript.buffer(
+                "bob",
+                annotation(&target, Action::Retract),
+                Duration::from_secs(60),
+            );
+        }
+        assert_eq!(transcript.pending.len(), MAX_BUFFERED);
+        // the oldest one was dropped
+        assert!(transcript.take_pending("b0").is_empty());
+
+        transcript.buffer("bob", annotation("gone", Action::Retract), Duration::ZERO);
+        assert!(transcript.take_pending("gone").is_empty());
+    }
+
+    #[test]
+    fn only_the_author_changes_a_message() {
+        let mut transcript = Transcript::new();
+        transcript
+            .append(entry("b1", "alice", false, "hi"))
+            .unwrap();
+        let amend = annotation("b1", Action::Amend("bye".to_string()));
+        assert!(matches!(
+            transcript.apply("bob", &amend).unwrap(),
+            Applied::Denied
+        ));
+        assert_eq!(transcript.get("b1").unwrap().msg, "hi");
+        assert!(matches!(
+            transcript.apply("alice", &amend).unwrap(),
+            Applied::Done
+        ));
+        assert_eq!(transcript.get("b1").unwrap().msg, "bye");
+    }
+
+    #[test]
+    fn retracted_messages_are_hidden() {
+        let mut transcript = Transcript::new();
+        transcript
+            .append(entry("b1", "alice", false, "secret"))
+            .unwrap();
+        transcript
+            .append(entry("b2", "alice", false, "public"))
+            .unwrap();
+        transcript
+            .apply("alice", &annotation("b1", Action::Retract))
+            .unwrap();
+        let shown: Vec<&str> = transcript
+            .conversation("alice")
+            .iter()
+            .map(|e| e.bid.as_str())
+            .collect();
+        assert_eq!(shown, vec!["b2"]);
+        let query = Query {
+            text: "secret".to_string(),
+            ..Default::default()
+        };
+        assert!(transcript.search(&query).is_empty());
+    }
+}
@@ -1,67 +1,264 @@
-use anyhow::Result;
-use bp7::{dtntime::DtnTimeHelpers, Bundle, EndpointID};
-use chrono::{Local, TimeZone};
+use crate::clock::{self, ClockSkew};
+use crate::ignore::{IgnoreList, Verdict};
+use crate::inspect;
+use crate::mentions::{Mention, Mentions};
+use crate::multipart::{self, Reassembler, Reassembly};
+use crate::options::BundleOptions;
+use crate::payload::{self, Preview};
+use crate::ping::{unix_now_ms, Pings};
+use crate::profiles::Profiles;
+use crate::proto::{Action, Annotation, Control, Echo, Part, Profile, Reply};
+use crate::recent::Recent;
+use crate::roster::unix_now;
+use crate::roster::Roster;
+use crate::settings::{Encoding, Settings};
+use crate::spool;
+use crate::stats::Stats;
+use crate::theme::Theme;
+use crate::timefmt::{format_delay, format_time};
+use crate::trace::Traces;
+use crate::transcript::{Applied, DeliveryState, Entry, Quote, Transcript};
+use anyhow::{bail, Result};
+use bp7::administrative_record::{
+    AdministrativeRecord, StatusReport, DELETED_BUNDLE, DELIVERED_BUNDLE,
+};
+use bp7::{Bundle, EndpointID};
 use crossbeam_channel::Receiver;
-use dtn7_plus::client::WsSendData;
-use dtn7_plus::sms::SMSBundle;
+use dtn7_plus::sms::{SMSBundle, SmsBuilder};
+use humantime::format_duration;
 use linefeed::terminal::DefaultTerminal;
 use linefeed::Interface;
-use std::convert::{TryFrom, TryInto};
-use std::error::Error;
-use std::io;
-use std::io::{stdout, Read, Write};
-use std::sync::Arc;
-use std::thread;
-use std::{collections::HashSet, time::Duration};
-use termion::{color::*, style};
-use ws::{Builder, CloseCode, Handler, Handshake, Message, Sender};
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use ws::{CloseCode, Handler, Handshake, Message, Sender};
 
 pub struct ChatConnection {
     pub localnode: EndpointID,
     pub out: Sender,
     pub subscribed: bool,
-    pub verbose: bool,
     pub iface: Arc<Interface<DefaultTerminal>>,
     pub recv: Receiver<WsCommand>,
+    pub roster: Arc<Mutex<Roster>>,
+    pub profiles: Arc<Mutex<Profiles>>,
+    pub me: Arc<Mutex<Profile>>,
+    pub mentions: Arc<Mutex<Mentions>>,
+    pub ignored: Arc<Mutex<IgnoreList>>,
+    pub transcript: Arc<Mutex<Transcript>>,
+    pub parts: Arc<Mutex<Reassembler>>,
+    pub recent: Arc<Mutex<Recent>>,
+    pub settings: Arc<Mutex<Settings>>,
+    pub skew: Arc<Mutex<ClockSkew>>,
+    pub pings: Arc<Mutex<Pings>>,
+    pub traces: Arc<Mutex<Traces>>,
+    pub stats: Arc<Mutex<Stats>>,
+    /// Shared copy of `subscribed` for the metrics endpoint
+    pub connected: Arc<Mutex<bool>>,
+    /// Used to answer control messages like pings
+    pub tx: crossbeam_channel::Sender<WsCommand>,
 }
 
 pub struct Outgoing {
     pub src: EndpointID,
     pub dst: EndpointID,
-    pub delivery_notification: bool,
+    pub options: BundleOptions,
     pub lifetime: Duration,
     pub data: Vec<u8>,
 }
 pub enum WsCommand {
     Text(String),
     SendData(Outgoing),
+    /// Hand an already built bundle to dtnd, e.g. one imported from a spool directory
+    Inject(Bundle),
+    /// Additionally write all outgoing bundles to this directory (sneakernet mode)
+    Spool(Option<PathBuf>),
 }
+pub fn send_sms(
+    tx: crossbeam_channel::Sender<WsCommand>,
+    src: EndpointID,
+    dst: EndpointID,
+    settings: &Settings,
+    msg: &str,
+) -> Result<()> {
+    let msg = msg.trim();
+    if settings.encoding_for(&dst) == Encoding::Plain {
+        let options = settings.options_for(&dst);
+        let data = Outgoing {
+            src,
+            dst,
+            options,
+            lifetime: settings.lifetime,
+            data: msg.as_bytes().to_vec(),
+        };
+        tx.send(WsCommand::SendData(data))?;
+        return Ok(());
+    }
+    if msg.len() > settings.part_size {
+        let parts = multipart::split(msg, settings.part_size);
+        if parts.len() > multipart::MAX_PARTS {
+            bail!(
+                "message too long, at most {} parts of {} bytes are allowed",
+                multipart::MAX_PARTS,
+                settings.part_size
+            );
+        }
+        let id = multipart::new_message_id();
+        let total = parts.len() as u32;
+        for (index, text) in parts.into_iter().enumerate() {
+            let part = Control::Part(Part {
+                id: id.clone(),
+                index: index as u32,
+                total,
+                text,
+            });
+            let data = Outgoing {
+                src: src.clone(),
+                dst: dst.clone(),
+                options: settings.options_for(&dst),
+                lifetime: settings.lifetime,
+                data: part.to_cbor(),
+            };
+            tx.send(WsCommand::SendData(data))?;
+        }
+        return Ok(());
+    }
+    let sms = serde_cbor::to_vec(
+        &SmsBuilder::new()
+            .compression(settings.compression)
+            .message(msg)
+            .build()?,
+    )?;
+    let options = settings.options_for(&dst);
+    let data = Outgoing {
+        src,
+        dst,
+        options,
+        lifetime: settings.lifetime,
+        data: sms,
+    };
+    tx.send(WsCommand::SendData(data))?;
+    Ok(())
+}
+/// Number of characters of the original message quoted in a reply
+const QUOTE_LEN: usize = 60;
+
+pub fn send_reply(
+    tx: crossbeam_channel::Sender<WsCommand>,
+    src: EndpointID,
+    dst: EndpointID,
+    settings: &Settings,
+    quoted: &Entry,
+    msg: &str,
+) -> Result<()> {
+    if settings.encoding_for(&dst) == Encoding::Plain {
+        // other tools know nothing about replies
+        return send_sms(tx, src, dst, settings, msg);
+    }
+    let first_line = quoted.msg.lines().next().unwrap_or_default();
+    let mut quote: String = first_line.chars().take(QUOTE_LEN).collect();
+    if quote.len() < quoted.msg.len() {
+        quote.push('…');
+    }
+    let reply = Control::Reply(Reply {
+        reply_to: quoted.bid.clone(),
+        quote_src: quoted.src.clone(),
+        quote,
+        text: msg.trim().to_string(),
+    });
+    let options = settings.options_for(&dst);
+    let data = Outgoing {
+        src,
+        dst,
+        options,
+        lifetime: settings.lifetime,
+        data: reply.to_cbor(),
+    };
+    tx.send(WsCommand::SendData(data))?;
+    Ok(())
+}
+
+/// Send a reaction, correction or retraction of an earlier message to `dst`
+pub fn send_annotation(
+    tx: crossbeam_channel::Sender<WsCommand>,
+    src: EndpointID,
+    dst: EndpointID,
+    settings: &Settings,
+    annotation: Annotation,
+) -> Result<()> {
+    if settings.encoding_for(&dst) == Encoding::Plain {
+        bail!("messages of plain text conversations cannot be changed");
+    }
+    let data = Outgoing {
+        src,
+        options: settings.options_for(&dst),
+        dst,
+        lifetime: settings.lifetime,
+        data: Control::Annotate(annotation).to_cbor(),
+    };
+    tx.send(WsCommand::SendData(data))?;
+    Ok(())
+}
+
+fn reply_entry(bndl: &Bundle, outgoing: bool, reply: Reply) -> Entry {
+    Entry {
+        quote: Some(Quote {
+            bid: reply.reply_to,
+            src: reply.quote_src,
+            text: reply.quote,
+        }),
+        ..Entry::new(bndl, outgoing, reply.text)
+    }
+}
+
+/// Print above the prompt if there is one, to stderr when running headless
+fn print_notice(iface: &Option<Arc<Interface<DefaultTerminal>>>, line: String) {
+    match iface {
+        Some(iface) => writeln!(iface, "{}", line).unwrap(),
+        None => eprintln!("{}", line),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn send_listener(
     recv: Receiver<WsCommand>,
     out: Sender,
-    iface: Arc<Interface<DefaultTerminal>>,
-    verbose: bool,
+    iface: Option<Arc<Interface<DefaultTerminal>>>,
+    spool_dir: Option<PathBuf>,
+    transcript: Arc<Mutex<Transcript>>,
+    recent: Arc<Mutex<Recent>>,
+    settings: Arc<Mutex<Settings>>,
+    stats: Arc<Mutex<Stats>>,
 ) {
+    let mut spool_dir = spool_dir;
+    let mut parts = Reassembler::new();
     for data in &recv {
         match data {
             WsCommand::Text(cmd) => {
                 out.send(cmd).expect("error sending command");
             }
+            WsCommand::Inject(mut bndl) => {
+                out.send(bndl.to_cbor()).expect("error injecting bundle");
+            }
+            WsCommand::Spool(dir) => {
+                spool_dir = dir;
+            }
             WsCommand::SendData(data) => {
-                let flags = if data.delivery_notification && !data.dst.to_string().contains("sms2")
-                {
-                    //println!("Delivery notification requested");
-                    bp7::flags::BundleControlFlags::BUNDLE_STATUS_REQUEST_DELIVERY.bits()
-                } else {
-                    0
+                let (theme, verbose) = {
+                    let settings = settings.lock().unwrap();
+                    (settings.theme, settings.verbose)
                 };
+                let mut flags = data.options.flags();
+                if data.dst.to_string().contains("sms2") {
+                    flags.remove(bp7::flags::BundleControlFlags::BUNDLE_STATUS_REQUEST_DELIVERY);
+                }
                 let mut bndl = bp7::bundle::BundleBuilder::new()
                     .primary(
                         bp7::primary::PrimaryBlockBuilder::new()
                             .source(data.src)
                             .destination(data.dst)
                             .lifetime(data.lifetime)
-                            .bundle_control_flags(flags)
+                            .bundle_control_flags(flags.bits())
                             .creation_timestamp(bp7::CreationTimestamp::now())
                             .build()
                             .unwrap(),
@@ -69,17 +266,105 @@ pub fn send_listener(
                     .payload(data.data)
                     .build()
                     .unwrap();
+                data.options.apply(&mut bndl);
                 //println!("{:?}", bndl);
                 let out_bytes = bndl.to_cbor();
+                let entry = if let Ok(sms) = SMSBundle::try_from(bndl.clone()) {
+                    Some(Entry::from_sms(&sms, true))
+                } else {
+                    match bndl.payload().and_then(|p| Control::from_cbor(p)) {
+                        Some(Control::Part(part)) => {
+                            match parts.add("", &bndl.id(), part, bndl.primary.lifetime) {
+                                Reassembly::Complete(bid, msg) => Some(Entry {
+                                    bid,
+                                    ..Entry::new(&bndl, true, msg)
+                                }),
+                                Reassembly::Missing(..) | Reassembly::Rejected => None,
+                            }
+                        }
+                        Some(Control::Reply(reply)) => Some(reply_entry(&bndl, true, reply)),
+                        None => match payload::preview(bndl.payload().unwrap_or(&Vec::new())) {
+                            // plain text conversation
+                            Preview::Text(text) => Some(Entry::new(&bndl, true, text)),
+                            _ => None,
+                        },
+                        Some(Control::Annotate(annotation)) => {
+                            let src = bndl.primary.source.node().unwrap_or_default();
+                            if let Err(e) = transcript.lock().unwrap().apply(&src, &annotation) {
+                                print_notice(
+                                    &iface,
+                                    format!(
+                                        "{}Could not write transcript: {}{}",
+                                        theme.error(),
+                                        e,
+                                        theme.reset()
+                                    ),
+                                );
+                            }
+                            None
+                        }
+                        _ => None,
+                    }
+                };
+                stats
+                    .lock()
+                    .unwrap()
+                    .sent(&bndl, out_bytes.len(), entry.is_some());
+                if let Some(entry) = entry {
+                    recent
+                        .lock()
+                        .unwrap()
+                        .push(unix_now(), bndl.clone(), Some(entry.bid.clone()));
+                    if let Err(e) = transcript.lock().unwrap().append(entry) {
+                        print_notice(
+                            &iface,
+                            format!(
+                                "{}Could not write transcript: {}{}",
+                                theme.error(),
+                                e,
+                                theme.reset()
+                            ),
+                        );
+                    }
+                }
                 if verbose {
-                    writeln!(
-                        iface,
-                        "{}Sent bundle with {} bytes.{}",
-                        Fg(Yellow),
-                        out_bytes.len(),
-                        style::Reset
-                    )
-                    .unwrap();
+                    print_notice(
+                        &iface,
+                        format!(
+                            "{}Sent bundle with {} bytes.{}",
+                            theme.notice(),
+                            out_bytes.len(),
+                            theme.reset()
+                        ),
+                    );
+                }
+                if let Some(dir) = &spool_dir {
+                    match spool::store(dir, &bndl.id(), &out_bytes) {
+                        Ok(path) => {
+                            if verbose {
+                                print_notice(
+                                    &iface,
+                                    format!(
+                                        "{}Spooled bundle to {}.{}",
+                                        theme.notice(),
+                                        path.display(),
+                                        theme.reset()
+                                    ),
+                                );
+                            }
+                        }
+                        Err(e) => {
+                            print_notice(
+                                &iface,
+                                format!(
+                                    "{}Could not spool bundle: {}{}",
+                                    theme.error(),
+                                    e,
+                                    theme.reset()
+                                ),
+                            );
+                        }
+                    }
                 }
                 out.send(out_bytes).expect("error sending echo response");
             }
@@ -88,104 +373,613 @@ pub fn send_listener(
 }
 
 impl ChatConnection {
-    fn on_bundle(&self, bndl: Bundle) -> Result<()> {
-        if bndl.is_administrative_record() {
-            if self.verbose {
+    fn theme(&self) -> Theme {
+        self.settings.lock().unwrap().theme
+    }
+    fn verbose(&self) -> bool {
+        self.settings.lock().unwrap().verbose
+    }
+    fn on_control(&self, bndl: &Bundle, ctrl: Control) -> Result<()> {
+        let theme = self.theme();
+        match ctrl {
+            Control::Presence(presence) => {
+                let src = bndl.primary.source.node().unwrap_or_default();
+                if let Some(group) = bndl.primary.destination.node() {
+                    self.roster
+                        .lock()
+                        .unwrap()
+                        .update(&group, &src, &presence, unix_now());
+                }
+                {
+                    let mut profiles = self.profiles.lock().unwrap();
+                    let status = profiles.get(&src).and_then(|p| p.status.clone());
+                    profiles.update(
+                        &src,
+                        Profile {
+                            node: src.clone(),
+                            nick: presence.nick.clone(),
+                            status,
+                        },
+                    );
+                }
+                if self.verbose() {
+                    writeln!(
+                        self.iface,
+                        "{}Presence of {} ({}) in {}{}",
+                        theme.notice(),
+                        presence.nick,
+                        src,
+                        bndl.primary.destination,
+                        theme.reset()
+                    )?;
+                }
+            }
+            Control::Part(part) => {
+                let src = bndl.primary.source.node().unwrap_or_default();
+                let dst = bndl.primary.destination.node().unwrap_or_default();
+                if bndl.primary.source == self.localnode && !self.verbose() {
+                    return Ok(());
+                }
+                let reassembly =
+                    self.parts
+                        .lock()
+                        .unwrap()
+                        .add(&src, &bndl.id(), part, bndl.primary.lifetime);
+                match reassembly {
+                    Reassembly::Complete(bid, msg) => {
+                        let outgoing = bndl.primary.source == self.localnode;
+                        self.show_message(
+                            bndl,
+                            Entry {
+                                bid,
+                                ..Entry::new(bndl, outgoing, msg)
+                            },
+                        )?;
+                    }
+                    Reassembly::Missing(received, total) => {
+                        writeln!(
+                            self.iface,
+                            "{}[{} > {}] receiving long message, {}/{} parts{}",
+                            theme.dim,
+                            self.profiles.lock().unwrap().display(&src),
+                            dst,
+                            received,
+                            total,
+                            theme.reset()
+                        )?;
+                    }
+                    Reassembly::Rejected => {
+                        if self.verbose() {
+                            writeln!(
+                                self.iface,
+                                "{}Dropped invalid message part from {}{}",
+                                theme.notice(),
+                                src,
+                                theme.reset()
+                            )?;
+                        }
+                    }
+                }
+            }
+            Control::Reply(reply) => {
+                if bndl.primary.source == self.localnode && !self.verbose() {
+                    return Ok(());
+                }
+                let outgoing = bndl.primary.source == self.localnode;
+                self.show_message(bndl, reply_entry(bndl, outgoing, reply))?;
+            }
+            Control::Annotate(annotation) => {
+                if bndl.primary.source == self.localnode {
+                    // already applied when sending
+                    return Ok(());
+                }
+                let src = bndl.primary.source.node().unwrap_or_default();
+                let applied = self.transcript.lock().unwrap().apply(&src, &annotation);
+                match applied {
+                    Ok(Applied::Done) => self.show_annotation(&src, &annotation)?,
+                    Ok(Applied::Unknown) => {
+                        self.transcript.lock().unwrap().buffer(
+                            &src,
+                            annotation,
+                            bndl.primary.lifetime,
+                        );
+                    }
+                    Ok(Applied::Denied) => {
+                        if self.verbose() {
+                            writeln!(
+                                self.iface,
+                                "{}Ignoring change of a message not sent by {}{}",
+                                theme.error(),
+                                src,
+                                theme.reset()
+                            )?;
+                        }
+                    }
+                    Err(e) => writeln!(
+                        self.iface,
+                        "{}Could not write transcript: {}{}",
+                        theme.error(),
+                        e,
+                        theme.reset()
+                    )?,
+                }
+            }
+            Control::Profile(profile) => {
+                let src = bndl.primary.source.node().unwrap_or_default();
+                let nick = profile.nick.clone();
+                if self.profiles.lock().unwrap().update(&src, profile) {
+                    if self.verbose() {
+                        writeln!(
+                            self.iface,
+                            "{}{} is now known as {}{}",
+                            theme.notice(),
+                            src,
+                            nick,
+                            theme.reset()
+                        )?;
+                    }
+                } else if self.verbose() {
+                    writeln!(
+                        self.iface,
+                        "{}Ignoring profile with mismatching node from {}{}",
+                        theme.error(),
+                        src,
+                        theme.reset()
+                    )?;
+                }
+            }
+            Control::Ping(ping) => {
+                if bndl.primary.source == self.localnode {
+                    return Ok(());
+                }
+                let echo = Control::Echo(Echo {
+                    id: ping.id,
+                    seq: ping.seq,
+                    received: unix_now_ms(),
+                });
+                let data = Outgoing {
+                    src: self.localnode.clone(),
+                    dst: bndl.primary.source.clone(),
+                    options: BundleOptions::default(),
+                    lifetime: bndl.primary.lifetime,
+                    data: echo.to_cbor(),
+                };
+                self.tx.send(WsCommand::SendData(data))?;
+                if self.verbose() {
+                    writeln!(
+                        self.iface,
+                        "{}Answered ping {} from {}{}",
+                        theme.notice(),
+                        ping.seq,
+                        bndl.primary.source,
+                        theme.reset()
+                    )?;
+                }
+            }
+            Control::Echo(echo) => {
+                let mut pings = self.pings.lock().unwrap();
+                if let Some((session, probe)) = pings.echo(&echo, unix_now_ms()) {
+                    writeln!(
+                        self.iface,
+                        "{}echo from {}: seq={}/{} rtt={} one-way≈{}{}{}",
+                        theme.dim,
+                        session.peer,
+                        probe.seq,
+                        session.count,
+                        format_duration(Duration::from_millis(probe.rtt)),
+                        if probe.one_way < 0 { "-" } else { "" },
+                        format_duration(Duration::from_millis(probe.one_way.unsigned_abs())),
+                        theme.reset()
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn on_status_report(&self, bndl: &Bundle, report: StatusReport) -> Result<()> {
+        let theme = self.theme();
+        let asserted = |pos: u32| {
+            report
+                .status_information
+                .get(pos as usize)
+                .is_some_and(|i| i.asserted)
+        };
+        let node = bndl.primary.source.node().unwrap_or_default();
+        if let Some((trace, hop)) = self.traces.lock().unwrap().report(&node, &report) {
+            let time_format = self.settings.lock().unwrap().time_format;
+            let mut events = Vec::new();
+            for (name, time) in [
+                ("received", hop.received),
+                ("forwarded", hop.forwarded),
+                ("delivered", hop.delivered),
+            ] {
+                if let Some(time) = time {
+                    events.push(format!("{} {}", name, format_time(time, time_format)));
+                }
+            }
+            writeln!(
+                self.iface,
+                "{}trace to {}: {} {}{}",
+                theme.dim,
+                trace.peer,
+                hop.node,
+                events.join(", "),
+                theme.reset()
+            )?;
+            return Ok(());
+        }
+        let state = if asserted(DELIVERED_BUNDLE) {
+            DeliveryState::Delivered
+        } else if asserted(DELETED_BUNDLE) {
+            DeliveryState::Deleted
+        } else {
+            return Ok(());
+        };
+        let bid = report.refbundle();
+        if state == DeliveryState::Delivered {
+            let time = report
+                .status_information
+                .get(DELIVERED_BUNDLE as usize)
+                .map_or(0, |i| i.time);
+            self.stats.lock().unwrap().delivered(&bid, time);
+        }
+        let known = match self.transcript.lock().unwrap().set_state(&bid, state) {
+            Ok(known) => known,
+            Err(e) => {
                 writeln!(
                     self.iface,
-                    "{}Handling of administrative records not yet implemented!{}",
-                    Fg(Red),
-                    style::Reset,
+                    "{}Could not write transcript: {}{}",
+                    theme.error(),
+                    e,
+                    theme.reset()
                 )?;
+                true
             }
-        } else if self.verbose || bndl.primary.source != self.localnode {
-            if let Ok(smsbundle) = SMSBundle::try_from(bndl) {
-                /*if self.verbose {
-                    writeln!(self.iface, "Bundle-Id: {}", bndl.id());
-                }*/
-                //let message = std::str::from_utf8(&data).unwrap().trim();
-                let message = smsbundle.msg();
-                let unixtime = smsbundle.creation_timestamp().dtntime().unix();
-                //let rfc3339 = bndl.primary.creation_timestamp.dtntime().string();
-                //let seq_no = bndl.primary.creation_timestamp.seqno();
-                let datetime = Local.timestamp(unixtime as i64, 0);
-                if smsbundle.dst().unwrap() == self.localnode.node().unwrap() {
+        };
+        if known && self.verbose() {
+            writeln!(
+                self.iface,
+                "{}Message {} {} at {}{}",
+                theme.notice(),
+                bid,
+                state,
+                bndl.primary.source,
+                theme.reset()
+            )?;
+        }
+        Ok(())
+    }
+    /// Apply ignore list and rate limit to a bundle from another node
+    fn should_show(&self, bndl: &Bundle) -> Result<bool> {
+        let theme = self.theme();
+        let src_node = bndl.primary.source.node().unwrap_or_default();
+        let dst_node = bndl.primary.destination.node().unwrap_or_default();
+        let verdict = self.ignored.lock().unwrap().check(
+            &src_node,
+            &bndl.primary.source.to_string(),
+            &dst_node,
+            &bndl.primary.destination.to_string(),
+        );
+        match verdict {
+            Verdict::Show => {
+                self.check_clock(bndl)?;
+                Ok(true)
+            }
+            Verdict::Ignored => {
+                if self.verbose() {
                     writeln!(
                         self.iface,
-                        "{}[{}{} {}{}{}] {}{}",
-                        Fg(LightWhite),
-                        Fg(Cyan),
-                        datetime.format("%F %T"),
-                        //datetime.format("%T"),
-                        Fg(LightGreen),
-                        smsbundle.src().unwrap(),
-                        Fg(LightWhite),
-                        termion::style::Reset,
-                        message
+                        "{}Ignored bundle from {}{}",
+                        theme.notice(),
+                        bndl.primary.source,
+                        theme.reset()
                     )?;
-                } else {
+                }
+                Ok(false)
+            }
+            Verdict::Muted(first) => {
+                if first {
                     writeln!(
                         self.iface,
-                        "{}[{}{} {}{} {}> {}{}{} ] {}{}",
-                        Fg(LightWhite),
-                        Fg(Cyan),
-                        datetime.format("%F %T"),
-                        //datetime.format("%T"),
-                        Fg(LightGreen),
-                        smsbundle.src().unwrap(),
-                        Fg(LightWhite),
-                        Fg(Green),
-                        smsbundle.dst().unwrap(),
-                        Fg(LightWhite),
-                        termion::style::Reset,
-                        message
+                        "{}{} exceeds the rate limit, muting{}",
+                        theme.error(),
+                        src_node,
+                        theme.reset()
                     )?;
                 }
+                Ok(false)
+            }
+        }
+    }
+    /// Track the clock of the sender and warn about creation times in the future
+    fn check_clock(&self, bndl: &Bundle) -> Result<()> {
+        let theme = self.theme();
+        let (created, estimated) = clock::creation_time(bndl);
+        if estimated {
+            if self.verbose() {
+                writeln!(
+                    self.iface,
+                    "{}{} has no clock, using receive time{}",
+                    theme.notice(),
+                    bndl.primary.source,
+                    theme.reset()
+                )?;
+            }
+            return Ok(());
+        }
+        let node = bndl.primary.source.node().unwrap_or_default();
+        if let Some(skew) = self.skew.lock().unwrap().record(&node, created) {
+            writeln!(
+                self.iface,
+                "{}Clock of {} is {} ahead of ours{}",
+                theme.error(),
+                node,
+                format_duration(Duration::from_secs(skew as u64)),
+                theme.reset()
+            )?;
+        }
+        Ok(())
+    }
+    fn show_annotation(&self, src: &str, annotation: &Annotation) -> Result<()> {
+        let theme = self.theme();
+        let target = self
+            .transcript
+            .lock()
+            .unwrap()
+            .get(&annotation.target)
+            .map(|e| e.msg.lines().next().unwrap_or_default().to_string())
+            .unwrap_or_default();
+        let sender = self.profiles.lock().unwrap().display(src);
+        let text = match &annotation.action {
+            Action::React(reaction) => format!("{} reacted {} to \"{}\"", sender, reaction, target),
+            Action::Amend(text) => format!("{} corrected a message: {}", sender, text),
+            Action::Retract => format!("{} retracted a message", sender),
+        };
+        writeln!(self.iface, "{}  * {}{}", theme.dim, text, theme.reset())?;
+        Ok(())
+    }
+    /// Record and print a received chat message
+    fn show_message(&self, bndl: &Bundle, entry: Entry) -> Result<()> {
+        let theme = self.theme();
+        if !entry.outgoing {
+            self.stats.lock().unwrap().message_received(&entry.src);
+            self.recent
+                .lock()
+                .unwrap()
+                .push(unix_now(), bndl.clone(), Some(entry.bid.clone()));
+            if let Err(e) = self.transcript.lock().unwrap().append(entry.clone()) {
+                writeln!(
+                    self.iface,
+                    "{}Could not write transcript: {}{}",
+                    theme.error(),
+                    e,
+                    theme.reset()
+                )?;
+            }
+        }
+        // indent continuation lines of multi-line messages
+        let mut message = entry.msg.replace('\n', "\n    ");
+        let unixtime = entry.time;
+        if !entry.outgoing {
+            let me = self.me.lock().unwrap();
+            let mut mentions = self.mentions.lock().unwrap();
+            if mentions.matches(&message, &[&me.node, &me.nick]) {
+                mentions.record(Mention {
+                    time: unixtime,
+                    src: entry.src.clone(),
+                    dst: entry.dst.clone(),
+                    msg: message.clone(),
+                });
+                message = theme.mention(&message);
+            }
+        }
+        let datetime = {
+            let settings = self.settings.lock().unwrap();
+            let mut created = format_time(unixtime, settings.time_format);
+            if entry.estimated {
+                // sender without clock, shown time is our receive time
+                if theme.accessible {
+                    created.push_str(" (estimated)");
+                } else {
+                    created.insert(0, '~');
+                }
+            }
+            if settings.show_delay {
+                let received = unix_now();
+                theme.delay(
+                    &created,
+                    &format_time(received, settings.time_format),
+                    &format_delay(unixtime, received),
+                )
             } else {
-                if self.verbose {
-                    writeln!(self.iface, "{}Unexpected payload!{}", Fg(Red), style::Reset)?;
+                created
+            }
+        };
+        let sender = self.profiles.lock().unwrap().display(&entry.src);
+        if let Some(quote) = &entry.quote {
+            let quote_src = self.profiles.lock().unwrap().display(&quote.src);
+            writeln!(self.iface, "{}", theme.quote(&quote_src, &quote.text))?;
+        }
+        let group = if entry.dst == self.localnode.node().unwrap() {
+            None
+        } else {
+            self.roster
+                .lock()
+                .unwrap()
+                .touch(&entry.dst, &entry.src, unixtime);
+            Some(entry.dst.as_str())
+        };
+        writeln!(
+            self.iface,
+            "{}",
+            theme.message(&datetime, &entry.src, &sender, group, &message)
+        )?;
+        if !entry.outgoing {
+            let pending = self.transcript.lock().unwrap().take_pending(&entry.bid);
+            for p in pending {
+                // the guard must be gone before show_annotation locks again
+                let applied = self.transcript.lock().unwrap().apply(&p.src, &p.annotation);
+                match applied {
+                    Ok(Applied::Done) => self.show_annotation(&p.src, &p.annotation)?,
+                    Ok(_) => {}
+                    Err(e) => writeln!(
+                        self.iface,
+                        "{}Could not write transcript: {}{}",
+                        theme.error(),
+                        e,
+                        theme.reset()
+                    )?,
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Show a bundle that is neither SMS nor control message, `/save` writes it to disk
+    fn show_payload(&self, bndl: Bundle) -> Result<()> {
+        let theme = self.theme();
+        let data = bndl.payload().cloned().unwrap_or_default();
+        let src = bndl.primary.source.node().unwrap_or_default();
+        let dst = bndl.primary.destination.node().unwrap_or_default();
+        let (created, _) = clock::creation_time(&bndl);
+        let time = format_time(created, self.settings.lock().unwrap().time_format);
+        let sender = self.profiles.lock().unwrap().display(&src);
+        let group = if dst == self.localnode.node().unwrap() {
+            None
+        } else {
+            Some(dst.as_str())
+        };
+        let (info, lines) = match payload::preview(&data) {
+            Preview::Text(text) => {
+                // plain text as sent by other bundle protocol stacks
+                let outgoing = bndl.primary.source == self.localnode;
+                return self.show_message(&bndl, Entry::new(&bndl, outgoing, text));
+            }
+            Preview::Cbor(diag) => {
+                let mut preview: String = diag.chars().take(payload::PREVIEW_CHARS).collect();
+                if preview.len() < diag.len() {
+                    preview.push('…');
+                }
+                (
+                    format!("CBOR, {} bytes: {}", data.len(), preview),
+                    Vec::new(),
+                )
+            }
+            Preview::Binary(lines) => (format!("{} bytes of binary data", data.len()), lines),
+        };
+        let n = self.recent.lock().unwrap().push(unix_now(), bndl, None);
+        let info = format!("{}{}{}", theme.dim, info, theme.reset());
+        writeln!(
+            self.iface,
+            "{}",
+            theme.message(&time, &src, &sender, group, &info)
+        )?;
+        for line in lines {
+            writeln!(self.iface, "{}    {}{}", theme.dim, line, theme.reset())?;
+        }
+        writeln!(
+            self.iface,
+            "{}    #{}, use /save {} <path> to store the payload{}",
+            theme.dim,
+            n,
+            n,
+            theme.reset()
+        )?;
+        Ok(())
+    }
+    /// Handle a bundle from dtnd, `size` is its encoded length
+    fn on_bundle(&self, bndl: Bundle, size: usize) -> Result<()> {
+        let theme = self.theme();
+        if bndl.primary.source != self.localnode {
+            self.stats.lock().unwrap().received(&bndl, size);
+        }
+        let (dump, time_format) = {
+            let settings = self.settings.lock().unwrap();
+            (settings.dump_bundles, settings.time_format)
+        };
+        if dump {
+            for line in inspect::describe(&bndl, time_format) {
+                writeln!(self.iface, "{}{}{}", theme.dim, line, theme.reset())?;
+            }
+        }
+        if bndl.is_administrative_record() {
+            match bndl
+                .payload()
+                .and_then(|p| serde_cbor::from_slice::<AdministrativeRecord>(p).ok())
+            {
+                Some(AdministrativeRecord::BundleStatusReport(report)) => {
+                    self.on_status_report(&bndl, report)?;
+                }
+                _ => {
+                    if self.verbose() {
+                        writeln!(
+                            self.iface,
+                            "{}Unsupported administrative record!{}",
+                            theme.error(),
+                            theme.reset(),
+                        )?;
+                    }
                 }
             }
+        } else if bndl.primary.source != self.localnode && !self.should_show(&bndl)? {
+            // dropped by ignore list or rate limit
+        } else if let Some(ctrl) = bndl.payload().and_then(|p| Control::from_cbor(p)) {
+            self.on_control(&bndl, ctrl)?;
+        } else if self.verbose() || bndl.primary.source != self.localnode {
+            if let Ok(smsbundle) = SMSBundle::try_from(bndl.clone()) {
+                let outgoing = smsbundle.src() == self.localnode.node();
+                self.show_message(smsbundle.bundle(), Entry::from_sms(&smsbundle, outgoing))?;
+            } else {
+                self.show_payload(bndl)?;
+            }
         }
         Ok(())
     }
 }
 impl Handler for ChatConnection {
     fn on_open(&mut self, _: Handshake) -> ws::Result<()> {
+        let theme = self.theme();
         writeln!(
             self.iface,
             "{}subscribing to {}{}",
-            Fg(Yellow),
+            theme.notice(),
             self.localnode,
-            style::Reset
+            theme.reset()
         )?;
         self.out.send(format!("/subscribe {}", self.localnode))?;
         self.out.send("/bundle".to_string())?;
         Ok(())
     }
 
+    fn on_close(&mut self, _: CloseCode, _: &str) {
+        self.subscribed = false;
+        *self.connected.lock().unwrap() = false;
+    }
+
     fn on_message(&mut self, msg: Message) -> ws::Result<()> {
+        let theme = self.theme();
         match msg {
             Message::Text(txt) => {
                 if txt == "subscribed" {
                     self.subscribed = true;
+                    *self.connected.lock().unwrap() = true;
                     writeln!(self.iface, "subscribed")?;
                 } else if txt.starts_with("200") {
                 } else {
                     writeln!(
                         self.iface,
                         "{}Unexpected response: {}{}",
-                        Fg(Red),
+                        theme.error(),
                         txt,
-                        style::Reset
+                        theme.reset()
                     )?;
                     self.out.close(CloseCode::Error)?;
                 }
             }
             Message::Binary(bin) => {
+                let size = bin.len();
                 let bndl: Bundle =
                     Bundle::try_from(bin).expect("Error decoding bundle from server");
-                self.on_bundle(bndl).expect("Error handling bundle");
+                self.on_bundle(bndl, size).expect("Error handling bundle");
             }
         }
         Ok(())